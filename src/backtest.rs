@@ -0,0 +1,1107 @@
+#![allow(non_snake_case)]
+
+use crate::block_index::{BlockIndex, Snapshot, Window};
+use crate::bootstrap::{bootstrap, BootstrapOptions, BootstrapPoint, BootstrapReport};
+use crate::error::Error;
+use crate::json_structure::{
+    BlockDiffMetadata, Data, Rad, Ray, StringOrWad, SubgraphVault, Vault, VaultLog, VaultSet,
+    VaultTransitionInnerType, VaultTransitionStatus, VaultTransitionWithMetadata, Wad,
+};
+use crate::liquidation_index::{Liquidation, LiquidationIndex};
+use crate::statistics;
+use crate::statistics::Summary;
+use crate::strategy::LiquidationStrategy;
+use crate::transition::{IlkTransitions, TransitionRow, TransitionTable};
+use crate::vault_filter::VaultFilter;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// liquidations keyed by vault id
+pub fn build_liquidation_index(vaults: &HashMap<String, Vault>) -> LiquidationIndex {
+    let mut liquidationTimestampListByVault = LiquidationIndex::new();
+    for (vault_id, vault) in vaults {
+        // a history without vaults has no liquidations, like the streaming loader
+        let liquidations: Vec<Liquidation> = vault
+            .vaults
+            .first()
+            .into_iter()
+            .flat_map(|vault| &vault.logs)
+            .filter_map(|vaultLog| match vaultLog {
+                VaultLog::LiquidationStartLog(log) => Some(Liquidation::from(log)),
+                _ => None,
+            })
+            .collect();
+        liquidationTimestampListByVault.insert(vault_id.clone(), liquidations);
+    }
+    liquidationTimestampListByVault
+}
+
+// all pairs of blocks where 0 < second - first < max_block_gap, see BlockIndex for other windows
+pub fn build_dataset(
+    allVaultsAtBlock: &HashMap<String, HashMap<String, VaultSet>>,
+    max_block_gap: u64,
+) -> Vec<Data<'_>> {
+    BlockIndex::new(allVaultsAtBlock).pairs(&Window::max_block_gap(max_block_gap))
+}
+
+pub fn build_transitions<'a>(
+    firstBlock: &str,
+    first: &'a VaultSet,
+    secondBlock: &str,
+    second: &'a VaultSet,
+    liquidationIndex: &LiquidationIndex,
+    missingVaultPolicy: MissingVaultPolicy,
+    vaultFilter: &VaultFilter,
+) -> Result<VaultTransitionWithMetadata<'a>, Error> {
+    let firstTimestamp = first.timestamp.0;
+    let secondTimestamp = second.timestamp.0;
+
+    let blockDiffMetadata: BlockDiffMetadata = BlockDiffMetadata {
+        firstBlock: firstBlock.to_string(),
+        firstPrice: first.price,
+        firstRate: first.rate,
+        firstLiquidationRatio: first.liquidationRatio,
+        firstTimestamp,
+        secondBlock: secondBlock.to_string(),
+        secondPrice: second.price,
+        secondRate: second.rate,
+        secondLiquidationRatio: second.liquidationRatio,
+        secondTimestamp,
+    };
+
+    // ordered by vault id so sums over the vaults don't depend on hashing
+    let mut vaultTransition: BTreeMap<&String, VaultTransitionInnerType> = BTreeMap::new();
+
+    let mut secondvaultsById: HashMap<&String, &SubgraphVault> = HashMap::new();
+    for vault in &second.resultArray {
+        secondvaultsById.insert(&vault.id, vault);
+    }
+
+    for vault in &first.resultArray {
+        if vaultFilter.accepts(vault, first) {
+            let secondVault = secondvaultsById.get(&vault.id).copied();
+            let status = match (secondVault, liquidationIndex.contains(&vault.id)) {
+                (None, _) => VaultTransitionStatus::Closed,
+                (Some(_), false) => VaultTransitionStatus::MissingHistory,
+                (Some(_), true) => VaultTransitionStatus::Continuing,
+            };
+            if missingVaultPolicy == MissingVaultPolicy::Fail {
+                match status {
+                    VaultTransitionStatus::Closed => {
+                        return Err(Error::MissingVault {
+                            vault: vault.id.clone(),
+                            block: secondBlock.to_string(),
+                        })
+                    }
+                    VaultTransitionStatus::MissingHistory => {
+                        return Err(Error::MissingLiquidationHistory {
+                            vault: vault.id.clone(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            let liquidations =
+                liquidationIndex.liquidations_between(&vault.id, firstTimestamp, secondTimestamp);
+            let liquidated = !liquidations.is_empty();
+            vaultTransition.insert(
+                &vault.id,
+                VaultTransitionInnerType {
+                    first: Some(vault),
+                    second: secondVault,
+                    status,
+                    liquidated,
+                    liquidationTimestamp: liquidations
+                        .first()
+                        .map(|liquidation| liquidation.timestamp),
+                    liquidatedDebt: liquidated
+                        .then(|| {
+                            StringOrWad::sum(
+                                liquidations.iter().map(|liquidation| liquidation.debt),
+                            )
+                        })
+                        .flatten(),
+                    seizedCollateral: liquidated
+                        .then(|| {
+                            StringOrWad::sum(
+                                liquidations
+                                    .iter()
+                                    .map(|liquidation| liquidation.collateral),
+                            )
+                        })
+                        .flatten(),
+                },
+            );
+        }
+    }
+
+    // vaults opened between the blocks have no first-block debt, they are only classified
+    let firstVaultIds: HashSet<&String> = first.resultArray.iter().map(|vault| &vault.id).collect();
+    for vault in &second.resultArray {
+        if vaultFilter.accepts(vault, second) && !firstVaultIds.contains(&vault.id) {
+            vaultTransition.insert(
+                &vault.id,
+                VaultTransitionInnerType {
+                    first: None,
+                    second: Some(vault),
+                    status: VaultTransitionStatus::Opened,
+                    liquidated: false,
+                    liquidationTimestamp: None,
+                    liquidatedDebt: None,
+                    seizedCollateral: None,
+                },
+            );
+        }
+    }
+
+    Ok(VaultTransitionWithMetadata {
+        meta: blockDiffMetadata,
+        vaultTransition,
+    })
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct CapitalAtRisk {
+    pub priceDropRatio: f64,
+    pub capitalAtRiskValueRisk: f64,
+    pub capitalAtRiskValueLiq: f64,
+    pub debtSum: f64,
+}
+
+// why a block pair did not produce a dRatio data point
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SkipReason {
+    InvalidPrice,
+    // price did not drop between the blocks
+    PriceRose,
+    ZeroEstimatedRisk,
+    Nan,
+    // a value has no fixed point representation or fixed point arithmetic overflowed
+    NotRepresentable,
+}
+
+// number type used for the capital at risk sums and the liquidation check
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Arithmetic {
+    #[default]
+    Float,
+    // wad/ray fixed point, with the liquidation check done like the Vat's safety check
+    FixedPoint,
+}
+
+impl FromStr for Arithmetic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Arithmetic, String> {
+        match s {
+            "float" => Ok(Arithmetic::Float),
+            "fixed-point" => Ok(Arithmetic::FixedPoint),
+            _ => Err(format!(
+                "unknown arithmetic {:?}, expected float or fixed-point",
+                s
+            )),
+        }
+    }
+}
+
+// what to do with vaults that are closed at the second block or missing from the vault
+// history; closed vaults still count with their first-block values, vaults missing from
+// the history never contribute to the capital at risk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissingVaultPolicy {
+    Skip,
+    // skip and report how many vaults were skipped
+    #[default]
+    Count,
+    // stop with MissingVault or MissingLiquidationHistory
+    Fail,
+}
+
+impl FromStr for MissingVaultPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MissingVaultPolicy, String> {
+        match s {
+            "skip" => Ok(MissingVaultPolicy::Skip),
+            "count" => Ok(MissingVaultPolicy::Count),
+            "fail" => Ok(MissingVaultPolicy::Fail),
+            _ => Err(format!(
+                "unknown missing vault policy {:?}, expected skip, count or fail",
+                s
+            )),
+        }
+    }
+}
+
+// what a liquidated vault adds to the actual capital at risk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActualLiquidation {
+    // the whole first-block debt
+    #[default]
+    FullDebt,
+    // debt taken by the liquidations between the blocks
+    LiquidatedDebt,
+    // collateral seized by the liquidations between the blocks, at the second-block price
+    SeizedCollateralValue,
+}
+
+impl FromStr for ActualLiquidation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ActualLiquidation, String> {
+        match s {
+            "full-debt" => Ok(ActualLiquidation::FullDebt),
+            "liquidated-debt" => Ok(ActualLiquidation::LiquidatedDebt),
+            "seized-collateral-value" => Ok(ActualLiquidation::SeizedCollateralValue),
+            _ => Err(format!(
+                "unknown actual liquidation {:?}, expected full-debt, liquidated-debt or seized-collateral-value",
+                s
+            )),
+        }
+    }
+}
+
+// settings shared by every strategy of a backtest
+#[derive(Clone, Debug, Default)]
+pub struct EvaluationOptions {
+    pub arithmetic: Arithmetic,
+    pub missingVaultPolicy: MissingVaultPolicy,
+    pub actualLiquidation: ActualLiquidation,
+    pub vaultFilter: VaultFilter,
+    pub bootstrap: Option<BootstrapOptions>,
+}
+
+// every capital at risk term uses first-block values, so closed vaults count like
+// continuing ones; vaults missing from the history are not in the table and never count
+pub fn capital_at_risk(
+    transitionTable: &TransitionTable,
+    strategy: &dyn LiquidationStrategy,
+    arithmetic: Arithmetic,
+    actualLiquidation: ActualLiquidation,
+) -> Result<CapitalAtRisk, SkipReason> {
+    match arithmetic {
+        Arithmetic::Float => capital_at_risk_float(transitionTable, strategy, actualLiquidation),
+        Arithmetic::FixedPoint => {
+            capital_at_risk_fixed_point(transitionTable, strategy, actualLiquidation)
+        }
+    }
+}
+
+// liquidated vaults whose logs have no amounts count with their whole first-block debt
+fn liquidated_value_float(
+    first: &TransitionRow,
+    secondPrice: f64,
+    actualLiquidation: ActualLiquidation,
+) -> f64 {
+    match actualLiquidation {
+        ActualLiquidation::FullDebt => first.vault.debt.0,
+        ActualLiquidation::LiquidatedDebt => first
+            .liquidatedDebt
+            .map_or(first.vault.debt.0, |liquidatedDebt| liquidatedDebt.0),
+        ActualLiquidation::SeizedCollateralValue => first
+            .seizedCollateral
+            .map_or(first.vault.debt.0, |seizedCollateral| {
+                seizedCollateral.0 * secondPrice
+            }),
+    }
+}
+
+fn liquidated_value_fixed_point(
+    first: &TransitionRow,
+    debt: Wad,
+    secondPrice: Ray,
+    actualLiquidation: ActualLiquidation,
+) -> Result<Wad, SkipReason> {
+    let value = match (
+        actualLiquidation,
+        first.liquidatedDebt,
+        first.seizedCollateral,
+    ) {
+        (ActualLiquidation::LiquidatedDebt, Some(liquidatedDebt), _) => liquidatedDebt.1,
+        (ActualLiquidation::SeizedCollateralValue, _, Some(seizedCollateral)) => seizedCollateral
+            .1
+            .and_then(|seizedCollateral| seizedCollateral.checked_mul::<27, 18>(secondPrice)),
+        _ => Some(debt),
+    };
+    value.ok_or(SkipReason::NotRepresentable)
+}
+
+fn capital_at_risk_float(
+    transitionTable: &TransitionTable,
+    strategy: &dyn LiquidationStrategy,
+    actualLiquidation: ActualLiquidation,
+) -> Result<CapitalAtRisk, SkipReason> {
+    let meta = &transitionTable.meta;
+    let secondPrice = meta.secondPrice.0;
+    let firstPrice = meta.firstPrice.0;
+    if !(firstPrice > 0.0 && secondPrice.is_finite()) {
+        return Err(SkipReason::InvalidPrice);
+    }
+    let price_drop_ratio = secondPrice / firstPrice;
+    if price_drop_ratio >= 1.0 {
+        return Err(SkipReason::PriceRose);
+    }
+
+    // calculated capital at risk value
+    let capitalAtRiskValueRisk = transitionTable
+        .rows
+        .iter()
+        .map(|first| {
+            let liquidationRatio = meta.firstLiquidationRatio.0;
+            let rate = meta.firstRate.0;
+            if first.vault.collateral.0 * secondPrice > first.vault.debt.0 * liquidationRatio * rate
+            {
+                0.0
+            } else {
+                strategy.estimate(&first.vault, price_drop_ratio, rate, liquidationRatio)
+            }
+        })
+        .sum::<f64>();
+
+    // actual capital at risk value
+    let capitalAtRiskValueLiq = transitionTable
+        .rows
+        .iter()
+        .map(|first| {
+            if first.liquidated {
+                liquidated_value_float(first, secondPrice, actualLiquidation)
+            } else {
+                0.0
+            }
+        })
+        .sum::<f64>();
+
+    // sum of all debt
+    let debtSum = transitionTable
+        .rows
+        .iter()
+        .map(|first| first.vault.debt.0)
+        .sum::<f64>();
+
+    Ok(CapitalAtRisk {
+        priceDropRatio: price_drop_ratio,
+        capitalAtRiskValueRisk,
+        capitalAtRiskValueLiq,
+        debtSum,
+    })
+}
+
+// same computation as capital_at_risk_float, with exact sums and liquidation check
+fn capital_at_risk_fixed_point(
+    transitionTable: &TransitionTable,
+    strategy: &dyn LiquidationStrategy,
+    actualLiquidation: ActualLiquidation,
+) -> Result<CapitalAtRisk, SkipReason> {
+    let meta = &transitionTable.meta;
+    let (Some(firstPrice), Some(secondPrice), Some(rate), Some(liquidationRatio)) = (
+        meta.firstPrice.1,
+        meta.secondPrice.1,
+        meta.firstRate.1,
+        meta.firstLiquidationRatio.1,
+    ) else {
+        return Err(SkipReason::NotRepresentable);
+    };
+    if firstPrice <= Ray::ZERO {
+        return Err(SkipReason::InvalidPrice);
+    }
+    if secondPrice >= firstPrice {
+        return Err(SkipReason::PriceRose);
+    }
+    let price_drop_ratio = secondPrice.to_f64() / firstPrice.to_f64();
+
+    let mut capitalAtRiskValueRisk = 0.0;
+    let mut capitalAtRiskValueLiq = Wad::ZERO;
+    let mut debtSum = Wad::ZERO;
+    for first in &transitionTable.rows {
+        let (Some(collateral), Some(debt)) = (first.vault.collateral.1, first.vault.debt.1) else {
+            return Err(SkipReason::NotRepresentable);
+        };
+        // ink * spot <= art * rate like the Vat, both exact in rad, with
+        // spot = price / liquidationRatio rounded down in ray like the Spotter
+        let collateralValue: Option<Rad> = secondPrice
+            .checked_div::<27, 27>(liquidationRatio)
+            .and_then(|spot| collateral.checked_mul(spot));
+        let debtValue: Option<Rad> = debt.checked_mul(rate);
+        let (Some(collateralValue), Some(debtValue)) = (collateralValue, debtValue) else {
+            return Err(SkipReason::NotRepresentable);
+        };
+        if collateralValue <= debtValue {
+            capitalAtRiskValueRisk += strategy.estimate(
+                &first.vault,
+                price_drop_ratio,
+                meta.firstRate.0,
+                meta.firstLiquidationRatio.0,
+            );
+        }
+        if first.liquidated {
+            capitalAtRiskValueLiq = capitalAtRiskValueLiq
+                .checked_add(liquidated_value_fixed_point(
+                    first,
+                    debt,
+                    secondPrice,
+                    actualLiquidation,
+                )?)
+                .ok_or(SkipReason::NotRepresentable)?;
+        }
+        debtSum = debtSum
+            .checked_add(debt)
+            .ok_or(SkipReason::NotRepresentable)?;
+    }
+
+    Ok(CapitalAtRisk {
+        priceDropRatio: price_drop_ratio,
+        capitalAtRiskValueRisk,
+        capitalAtRiskValueLiq: capitalAtRiskValueLiq.to_f64(),
+        debtSum: debtSum.to_f64(),
+    })
+}
+
+// number of transitions of a block pair by status, continuing vaults are not counted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultStatusCounts {
+    pub closed: u64,
+    pub opened: u64,
+    pub missingHistory: u64,
+}
+
+impl VaultStatusCounts {
+    pub fn of(vaultTransitionWithMetadata: &VaultTransitionWithMetadata) -> VaultStatusCounts {
+        let mut counts = VaultStatusCounts::default();
+        for vaultTransitionInner in vaultTransitionWithMetadata.vaultTransition.values() {
+            match vaultTransitionInner.status {
+                VaultTransitionStatus::Continuing => {}
+                VaultTransitionStatus::Closed => counts.closed += 1,
+                VaultTransitionStatus::Opened => counts.opened += 1,
+                VaultTransitionStatus::MissingHistory => counts.missingHistory += 1,
+            }
+        }
+        counts
+    }
+
+    fn add(&mut self, other: &VaultStatusCounts) {
+        self.closed += other.closed;
+        self.opened += other.opened;
+        self.missingHistory += other.missingHistory;
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DRatioReport {
+    pub strategy: String,
+    pub threshold: Option<f64>,
+    pub coefficient: Option<f64>,
+    // None for the aggregate over all selected ilks
+    pub ilk: Option<String>,
+    pub dRatioMedian: f64,
+    pub dRatioMean: f64,
+    pub dRatio: f64,
+    pub plusSum: f64,
+    pub minusSum: f64,
+    pub plusCount: u32,
+    pub minusCount: u32,
+    pub validDataPointCount: u32,
+    // summed over all evaluated pairs, only with the count missing vault policy
+    pub closedVaultCount: Option<u64>,
+    pub openedVaultCount: Option<u64>,
+    pub missingHistoryVaultCount: Option<u64>,
+    // distribution of the signed dRatio of the valid data points
+    pub dRatioStatistics: Summary,
+    // only when bootstrapping, None without valid data points
+    pub bootstrap: Option<BootstrapReport>,
+    pub elapsedMillis: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BacktestReport {
+    pub ilks: Vec<DRatioReport>,
+    pub aggregate: DRatioReport,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IlkSelection {
+    All,
+    Only(Vec<String>),
+}
+
+impl IlkSelection {
+    // sorted list of selected ilks present in at least one loaded snapshot, explicitly
+    // selected ilks that are never present are an error; a window without pairs still
+    // resolves, its ilks just get no tables
+    pub fn resolve(&self, snapshots: &[Snapshot]) -> Result<Vec<String>, Error> {
+        let mut ilks: Vec<String> = vec![];
+        for snapshot in snapshots {
+            for ilk in snapshot.vaultSets.keys() {
+                if !ilks.contains(ilk) && self.contains(ilk) {
+                    ilks.push(ilk.to_string());
+                }
+            }
+        }
+        ilks.sort();
+        if let IlkSelection::Only(selected) = self {
+            if let Some(missing) = selected.iter().find(|ilk| !ilks.contains(ilk)) {
+                return Err(Error::MissingIlk {
+                    ilk: missing.to_string(),
+                    block: None,
+                });
+            }
+        }
+        Ok(ilks)
+    }
+
+    pub fn contains(&self, ilk: &str) -> bool {
+        match self {
+            IlkSelection::All => true,
+            IlkSelection::Only(ilks) => ilks.iter().any(|selected| selected == ilk),
+        }
+    }
+}
+
+#[derive(Default)]
+struct DRatioAccumulator {
+    dRatio: f64,
+    dRatioList: Vec<f64>,
+    points: Vec<BootstrapPoint>,
+    validDataPointCount: u32,
+    plusCount: u32,
+    plusSum: f64,
+    minusCount: u32,
+    minusSum: f64,
+    vaultStatusCounts: Option<VaultStatusCounts>,
+    bootstrap: Option<BootstrapOptions>,
+    elapsed: Duration,
+}
+
+impl DRatioAccumulator {
+    fn new(options: &EvaluationOptions) -> DRatioAccumulator {
+        DRatioAccumulator {
+            vaultStatusCounts: (options.missingVaultPolicy == MissingVaultPolicy::Count)
+                .then(VaultStatusCounts::default),
+            bootstrap: options.bootstrap,
+            ..DRatioAccumulator::default()
+        }
+    }
+
+    fn count_vaults(&mut self, counts: &VaultStatusCounts) {
+        if let Some(total) = self.vaultStatusCounts.as_mut() {
+            total.add(counts);
+        }
+    }
+
+    fn push(
+        &mut self,
+        firstTimestamp: u64,
+        capitalAtRiskValueLiq: f64,
+        capitalAtRiskValueRisk: f64,
+        dRatio: f64,
+    ) {
+        self.dRatio += dRatio.abs();
+        let plus = capitalAtRiskValueLiq > capitalAtRiskValueRisk;
+        if plus {
+            self.plusCount += 1;
+            self.plusSum += dRatio;
+        } else {
+            self.minusCount += 1;
+            self.minusSum += dRatio;
+        }
+        self.validDataPointCount += 1;
+        self.dRatioList.push(dRatio.abs());
+        self.points.push(BootstrapPoint {
+            firstTimestamp,
+            dRatio,
+            plus,
+        });
+    }
+
+    fn merge(&mut self, other: &DRatioAccumulator) {
+        self.dRatio += other.dRatio;
+        self.dRatioList.extend_from_slice(&other.dRatioList);
+        self.points.extend_from_slice(&other.points);
+        self.validDataPointCount += other.validDataPointCount;
+        self.plusCount += other.plusCount;
+        self.plusSum += other.plusSum;
+        self.minusCount += other.minusCount;
+        self.minusSum += other.minusSum;
+        if let Some(counts) = &other.vaultStatusCounts {
+            self.count_vaults(counts);
+        }
+        self.elapsed += other.elapsed;
+    }
+
+    fn finish(self, strategy: &dyn LiquidationStrategy, ilk: Option<String>) -> DRatioReport {
+        let dRatioMedian = statistics::upper_median(&self.dRatioList).unwrap_or(f64::NAN);
+        let signedDRatioList: Vec<f64> = self.points.iter().map(|point| point.dRatio).collect();
+        let dRatioMean = self.dRatio / (self.validDataPointCount as f64) * 100.0;
+
+        DRatioReport {
+            strategy: strategy.label(),
+            threshold: strategy.parameter("threshold"),
+            coefficient: strategy.parameter("coefficient"),
+            ilk,
+            dRatioMedian,
+            dRatioMean,
+            dRatio: self.dRatio,
+            plusSum: self.plusSum,
+            minusSum: self.minusSum,
+            plusCount: self.plusCount,
+            minusCount: self.minusCount,
+            validDataPointCount: self.validDataPointCount,
+            closedVaultCount: self.vaultStatusCounts.map(|counts| counts.closed),
+            openedVaultCount: self.vaultStatusCounts.map(|counts| counts.opened),
+            missingHistoryVaultCount: self.vaultStatusCounts.map(|counts| counts.missingHistory),
+            dRatioStatistics: Summary::of(&signedDRatioList),
+            bootstrap: self
+                .bootstrap
+                .and_then(|options| bootstrap(&self.points, &options)),
+            elapsedMillis: self.elapsed.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+// one evaluated (firstBlock, secondBlock, ilk) pair, exported as a JSON Lines record
+#[derive(Debug, Serialize)]
+pub struct DataPointTrace {
+    pub strategy: String,
+    pub ilk: String,
+    pub meta: BlockDiffMetadata,
+    pub priceDropRatio: Option<f64>,
+    pub capitalAtRiskValueRisk: Option<f64>,
+    pub capitalAtRiskValueLiq: Option<f64>,
+    pub debtSum: Option<f64>,
+    // (capitalAtRiskValueLiq - capitalAtRiskValueRisk) / debtSum
+    pub dRatio: Option<f64>,
+    pub skipReason: Option<SkipReason>,
+    // only with the count missing vault policy
+    pub vaultStatusCounts: Option<VaultStatusCounts>,
+}
+
+// sets the worker threads of rayon's global pool, call it once per run before anything
+// runs in parallel, None uses every core
+pub fn init_thread_pool(threads: Option<usize>) -> Result<(), Error> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .build_global()
+        .map_err(|e| Error::ThreadPool {
+            message: e.to_string(),
+        })
+}
+
+// builds the transition tables of the ilks (see IlkSelection::resolve) once so every
+// strategy can reuse them, pairs are built in parallel on the global thread pool
+pub fn build_ilk_transitions(
+    dataset: &[Data],
+    liquidationIndex: &LiquidationIndex,
+    ilks: &[String],
+    options: &EvaluationOptions,
+) -> Result<Vec<IlkTransitions>, Error> {
+    let mut ilkTransitions: Vec<IlkTransitions> = vec![];
+    for ilk in ilks {
+        let results: Vec<Result<Option<TransitionTable>, Error>> = dataset
+            .par_iter()
+            .map(|row| {
+                // pairs where either snapshot lacks the ilk are skipped for that ilk
+                let (Some(first), Some(second)) = (
+                    row.vaultsAtFirstBlock.get(ilk),
+                    row.vaultsAtSecondBlock.get(ilk),
+                ) else {
+                    return Ok(None);
+                };
+                let vaultTransitionWithMetadata = build_transitions(
+                    &row.firstBlock,
+                    first,
+                    &row.secondBlock,
+                    second,
+                    liquidationIndex,
+                    options.missingVaultPolicy,
+                    &options.vaultFilter,
+                )?;
+                Ok(Some(TransitionTable::new(&vaultTransitionWithMetadata)))
+            })
+            .collect();
+        // report the error of the earliest pair, whatever the thread scheduling
+        let mut tables: Vec<TransitionTable> = vec![];
+        for result in results {
+            tables.extend(result?);
+        }
+        ilkTransitions.push(IlkTransitions {
+            ilk: ilk.to_string(),
+            tables,
+        });
+    }
+    Ok(ilkTransitions)
+}
+
+pub fn evaluate(
+    dataset: &[Data],
+    liquidationIndex: &LiquidationIndex,
+    strategy: &dyn LiquidationStrategy,
+    ilks: &[String],
+    options: &EvaluationOptions,
+) -> Result<BacktestReport, Error> {
+    evaluate_with_trace(dataset, liquidationIndex, strategy, ilks, options, None)
+}
+
+// same as evaluate, but hands every evaluated pair to `trace` when given
+pub fn evaluate_with_trace(
+    dataset: &[Data],
+    liquidationIndex: &LiquidationIndex,
+    strategy: &dyn LiquidationStrategy,
+    ilks: &[String],
+    options: &EvaluationOptions,
+    trace: Option<&mut dyn FnMut(DataPointTrace)>,
+) -> Result<BacktestReport, Error> {
+    let ilkTransitions = build_ilk_transitions(dataset, liquidationIndex, ilks, options)?;
+    Ok(evaluate_transitions(
+        &ilkTransitions,
+        strategy,
+        options,
+        trace,
+    ))
+}
+
+// evaluates every strategy against transitions built once
+pub fn evaluate_all(
+    dataset: &[Data],
+    liquidationIndex: &LiquidationIndex,
+    strategies: &[Box<dyn LiquidationStrategy>],
+    ilks: &[String],
+    options: &EvaluationOptions,
+    trace: Option<&mut dyn FnMut(DataPointTrace)>,
+) -> Result<Vec<BacktestReport>, Error> {
+    let ilkTransitions = build_ilk_transitions(dataset, liquidationIndex, ilks, options)?;
+    evaluate_strategies(&ilkTransitions, strategies, options, trace)
+}
+
+// strategies run in parallel on the global thread pool, reports (and traces) come back
+// in strategy order
+pub fn evaluate_strategies(
+    ilkTransitions: &[IlkTransitions],
+    strategies: &[Box<dyn LiquidationStrategy>],
+    options: &EvaluationOptions,
+    mut trace: Option<&mut dyn FnMut(DataPointTrace)>,
+) -> Result<Vec<BacktestReport>, Error> {
+    let tracing = trace.is_some();
+    let results: Vec<(BacktestReport, Vec<DataPointTrace>)> = strategies
+        .par_iter()
+        .map(|strategy| {
+            let mut traces: Vec<DataPointTrace> = vec![];
+            let mut collect = |dataPointTrace: DataPointTrace| traces.push(dataPointTrace);
+            let report = evaluate_transitions(
+                ilkTransitions,
+                strategy.as_ref(),
+                options,
+                if tracing { Some(&mut collect) } else { None },
+            );
+            (report, traces)
+        })
+        .collect();
+
+    let mut reports: Vec<BacktestReport> = vec![];
+    for (report, traces) in results {
+        if let Some(trace) = trace.as_mut() {
+            traces.into_iter().for_each(&mut *trace);
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+pub fn evaluate_transitions(
+    ilkTransitions: &[IlkTransitions],
+    strategy: &dyn LiquidationStrategy,
+    options: &EvaluationOptions,
+    mut trace: Option<&mut dyn FnMut(DataPointTrace)>,
+) -> BacktestReport {
+    let label = strategy.label();
+    let mut accumulators: Vec<DRatioAccumulator> = vec![];
+
+    for IlkTransitions { ilk, tables } in ilkTransitions {
+        let start = Instant::now();
+        let mut accumulator = DRatioAccumulator::new(options);
+        for transitionTable in tables {
+            let vaultStatusCounts = (options.missingVaultPolicy == MissingVaultPolicy::Count)
+                .then_some(transitionTable.vaultStatusCounts);
+            if let Some(counts) = &vaultStatusCounts {
+                accumulator.count_vaults(counts);
+            }
+
+            let capitalAtRisk = capital_at_risk(
+                transitionTable,
+                strategy,
+                options.arithmetic,
+                options.actualLiquidation,
+            );
+            let mut dRatio: Option<f64> = None;
+            let skipReason = match capitalAtRisk {
+                Err(skipReason) => Some(skipReason),
+                // only think in case estimated risk is above zero. otherwise, the data point is invalid.
+                Ok(point) if point.capitalAtRiskValueRisk <= 0.0 => {
+                    Some(SkipReason::ZeroEstimatedRisk)
+                }
+                Ok(point) => {
+                    let maybeNan = (point.capitalAtRiskValueLiq - point.capitalAtRiskValueRisk)
+                        / point.debtSum;
+                    dRatio = Some(maybeNan);
+                    // the trace holds the capital at risk of the pair
+                    if maybeNan.is_nan() {
+                        Some(SkipReason::Nan)
+                    } else {
+                        accumulator.push(
+                            transitionTable.meta.firstTimestamp,
+                            point.capitalAtRiskValueLiq,
+                            point.capitalAtRiskValueRisk,
+                            maybeNan,
+                        );
+                        None
+                    }
+                }
+            };
+
+            if let Some(trace) = trace.as_mut() {
+                let point = capitalAtRisk.ok();
+                trace(DataPointTrace {
+                    strategy: label.clone(),
+                    ilk: ilk.to_string(),
+                    meta: transitionTable.meta.clone(),
+                    priceDropRatio: point.map(|point| point.priceDropRatio),
+                    capitalAtRiskValueRisk: point.map(|point| point.capitalAtRiskValueRisk),
+                    capitalAtRiskValueLiq: point.map(|point| point.capitalAtRiskValueLiq),
+                    debtSum: point.map(|point| point.debtSum),
+                    dRatio,
+                    skipReason,
+                    vaultStatusCounts,
+                });
+            }
+        }
+        accumulator.elapsed = start.elapsed();
+        accumulators.push(accumulator);
+    }
+
+    let mut total = DRatioAccumulator::new(options);
+    for accumulator in &accumulators {
+        total.merge(accumulator);
+    }
+    BacktestReport {
+        ilks: ilkTransitions
+            .iter()
+            .zip(accumulators)
+            .map(|(ilkTransitions, accumulator)| {
+                accumulator.finish(strategy, Some(ilkTransitions.ilk.clone()))
+            })
+            .collect(),
+        aggregate: total.finish(strategy, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::ThresholdStrategy;
+
+    fn vault_set(timestamp: u64, price: &str, vaults: &[(&str, &str, &str)]) -> VaultSet {
+        let resultArray: Vec<serde_json::Value> = vaults
+            .iter()
+            .map(|(id, collateral, debt)| {
+                serde_json::json!({
+                    "id": id,
+                    "collateral": collateral,
+                    "debt": debt,
+                    "cdpId": null,
+                    "updatedAt": null,
+                    "updatedAtBlock": null,
+                    "updatedAtTransaction": null,
+                    "safetyLevel": "120",
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "timestamp": timestamp.to_string(),
+            "resultArray": resultArray,
+            "price": price,
+            "rate": "1",
+            "liquidationRatio": "1.5",
+        }))
+        .unwrap()
+    }
+
+    // 0x1 continues, 0x5 is liquidated between the blocks and gone from the second
+    // snapshot, 0x6 has no vault history and 0x7 is opened
+    fn fixture() -> (VaultSet, VaultSet, LiquidationIndex) {
+        let first = vault_set(
+            1000,
+            "2000",
+            &[
+                ("0x1", "10", "5000"),
+                ("0x5", "10", "11000"),
+                ("0x6", "1", "1000"),
+            ],
+        );
+        let second = vault_set(
+            2000,
+            "1500",
+            &[
+                ("0x1", "10", "5000"),
+                ("0x6", "1", "1000"),
+                ("0x7", "3", "100"),
+            ],
+        );
+        let liquidationIndex: LiquidationIndex = [
+            ("0x1".to_string(), vec![]),
+            ("0x5".to_string(), vec![Liquidation::at(1500)]),
+            ("0x7".to_string(), vec![]),
+        ]
+        .into_iter()
+        .collect();
+        (first, second, liquidationIndex)
+    }
+
+    fn table(
+        first: &VaultSet,
+        second: &VaultSet,
+        liquidationIndex: &LiquidationIndex,
+        policy: MissingVaultPolicy,
+    ) -> Result<TransitionTable, Error> {
+        build_transitions(
+            "100",
+            first,
+            "200",
+            second,
+            liquidationIndex,
+            policy,
+            &VaultFilter::default(),
+        )
+        .map(|transitions| TransitionTable::new(&transitions))
+    }
+
+    #[test]
+    fn build_transitions_classifies_every_vault() {
+        let (first, second, liquidationIndex) = fixture();
+        let transitions = build_transitions(
+            "100",
+            &first,
+            "200",
+            &second,
+            &liquidationIndex,
+            MissingVaultPolicy::Count,
+            &VaultFilter::default(),
+        )
+        .unwrap();
+        let statuses: Vec<(&str, VaultTransitionStatus, bool)> = transitions
+            .vaultTransition
+            .iter()
+            .map(|(id, inner)| (id.as_str(), inner.status, inner.liquidated))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("0x1", VaultTransitionStatus::Continuing, false),
+                ("0x5", VaultTransitionStatus::Closed, true),
+                ("0x6", VaultTransitionStatus::MissingHistory, false),
+                ("0x7", VaultTransitionStatus::Opened, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn closed_vaults_count_towards_capital_at_risk() {
+        let (first, second, liquidationIndex) = fixture();
+        for policy in [MissingVaultPolicy::Skip, MissingVaultPolicy::Count] {
+            let table = table(&first, &second, &liquidationIndex, policy).unwrap();
+            let ids: Vec<&str> = table.rows.iter().map(|row| row.vault.id.as_str()).collect();
+            assert_eq!(ids, ["0x1", "0x5"]);
+            let debts: Vec<f64> = table.rows.iter().map(|row| row.vault.debt.0).collect();
+            assert_eq!(debts, [5000.0, 11000.0]);
+            assert_eq!(
+                table.vaultStatusCounts,
+                VaultStatusCounts {
+                    closed: 1,
+                    opened: 1,
+                    missingHistory: 1,
+                }
+            );
+
+            let strategy = ThresholdStrategy {
+                threshold: 100.0,
+                coefficient: 0.5,
+            };
+            for arithmetic in [Arithmetic::Float, Arithmetic::FixedPoint] {
+                let capitalAtRisk =
+                    capital_at_risk(&table, &strategy, arithmetic, ActualLiquidation::FullDebt)
+                        .unwrap();
+                assert_eq!(capitalAtRisk.capitalAtRiskValueLiq, 11000.0);
+                // only 0x5 is below the liquidation ratio at the second price
+                assert_eq!(capitalAtRisk.capitalAtRiskValueRisk, 5500.0);
+                assert_eq!(capitalAtRisk.debtSum, 16000.0);
+            }
+        }
+    }
+
+    #[test]
+    fn ilks_resolve_against_the_snapshots_even_without_pairs() {
+        let (first, second, liquidationIndex) = fixture();
+        let allVaultsAtBlock: HashMap<String, HashMap<String, VaultSet>> = [
+            (
+                "100".to_string(),
+                HashMap::from([("ETH-A".to_string(), first)]),
+            ),
+            (
+                "200".to_string(),
+                HashMap::from([("WBTC-A".to_string(), second)]),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let blockIndex = BlockIndex::new(&allVaultsAtBlock);
+        let selection = IlkSelection::Only(vec!["WBTC-A".to_string(), "ETH-A".to_string()]);
+        let ilks = selection.resolve(blockIndex.snapshots()).unwrap();
+        assert_eq!(ilks, ["ETH-A", "WBTC-A"]);
+        assert_eq!(
+            IlkSelection::All.resolve(blockIndex.snapshots()).unwrap(),
+            ilks
+        );
+        let missing = IlkSelection::Only(vec!["LINK-A".to_string()]);
+        assert!(matches!(
+            missing.resolve(blockIndex.snapshots()),
+            Err(Error::MissingIlk { ilk, block: None }) if ilk == "LINK-A"
+        ));
+
+        // the window has no pairs, every ilk still gets its empty tables
+        let dataset = blockIndex.pairs(&Window::FixedBlockGap { gap: 7 });
+        let ilkTransitions = build_ilk_transitions(
+            &dataset,
+            &liquidationIndex,
+            &ilks,
+            &EvaluationOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(ilkTransitions.len(), 2);
+        assert!(ilkTransitions.iter().all(|ilk| ilk.tables.is_empty()));
+    }
+
+    #[test]
+    fn fail_policy_stops_on_closed_and_missing_history_vaults() {
+        let (first, second, liquidationIndex) = fixture();
+        let result = table(&first, &second, &liquidationIndex, MissingVaultPolicy::Fail);
+        assert!(matches!(result, Err(Error::MissingVault { vault, .. }) if vault == "0x5"));
+
+        let first = vault_set(1000, "2000", &[("0x1", "10", "5000"), ("0x6", "1", "1000")]);
+        let result = table(&first, &second, &liquidationIndex, MissingVaultPolicy::Fail);
+        assert!(
+            matches!(result, Err(Error::MissingLiquidationHistory { vault }) if vault == "0x6")
+        );
+
+        // opened vaults never fail the run
+        let first = vault_set(1000, "2000", &[("0x1", "10", "5000")]);
+        let second = vault_set(2000, "1500", &[("0x1", "10", "5000"), ("0x7", "3", "100")]);
+        let table = table(&first, &second, &liquidationIndex, MissingVaultPolicy::Fail).unwrap();
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.vaultStatusCounts.opened, 1);
+    }
+}
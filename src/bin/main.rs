@@ -1,421 +1,1190 @@
 #![allow(non_snake_case)]
 
-use rust_subgraph_tools::json_structure::{
-    BlockDiffMetadata, Data, SubgraphVault, Vault, VaultSet, VaultTransitionInnerType,
-    VaultTransitionWithMetadata,
+use clap::{Args, Parser, Subcommand};
+use rust_subgraph_tools::backtest::{
+    build_ilk_transitions, evaluate_strategies, init_thread_pool, ActualLiquidation, Arithmetic,
+    DRatioReport, DataPointTrace, EvaluationOptions, IlkSelection, MissingVaultPolicy,
 };
+use rust_subgraph_tools::block_index::{parse_duration, BlockIndex, Window};
+use rust_subgraph_tools::bootstrap::{BootstrapOptions, DEFAULT_BLOCK_LENGTH, DEFAULT_CONFIDENCE};
+use rust_subgraph_tools::config::{
+    read_run_config, BootstrapConfig, DataSources, ValidationConfig,
+};
+use rust_subgraph_tools::error;
+use rust_subgraph_tools::liquidation_index::LiquidationIndex;
+use rust_subgraph_tools::loader::{
+    block_stamps, build_snapshot_cache, read_block_dir, read_dir, read_json_dir,
+    read_liquidation_index_from_file, CacheUsage, VaultSetsByBlock,
+};
+use rust_subgraph_tools::optimizer::{
+    optimize, Method, Objective, OptimizerOptions, ParameterBound, StrategyFamily,
+    DEFAULT_UNDERESTIMATION_WEIGHT,
+};
+use rust_subgraph_tools::report::{write_reports, write_search, write_validation, TraceWriter};
+use rust_subgraph_tools::snapshot_cache::FileStamp;
+use rust_subgraph_tools::strategy::{LiquidationStrategy, ThresholdStrategy};
+use rust_subgraph_tools::transition::{
+    read_transition_cache, write_transition_cache, IlkTransitions, TransitionCache,
+    TransitionCacheKey, TRANSITION_CACHE_VERSION,
+};
+use rust_subgraph_tools::validation::{
+    cross_validate, fit_best, Folds, ValidationOptions, ValidationReport,
+};
+use rust_subgraph_tools::vault_filter::VaultFilter;
 use std::collections::HashMap;
 use std::error::Error;
-use std::fs;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Instant;
 
-fn read_vault_history_from_file<P: AsRef<Path>>(
-    path: P,
-) -> Result<HashMap<String, Vault>, Box<dyn Error>> {
-    let start = Instant::now();
-    // Open the file in read-only mode with buffer.
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+#[derive(Parser)]
+#[command(about = "Backtest expected vault liquidation against actual liquidation")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct DataArgs {
+    /// vaultHistory.json exported from the subgraph
+    #[arg(long, default_value = "../subgraph-tools/data/jsons/vaultHistory.json")]
+    vault_history: PathBuf,
+
+    /// directory holding one `<block>/<file>.json` vault set per block
+    #[arg(long, default_value = "../subgraph-tools/data/vaultSet")]
+    vault_set: String,
+}
+
+#[derive(Args)]
+struct IlkArgs {
+    /// ilk to evaluate, can be repeated
+    #[arg(long = "ilk", default_values_t = ["ETH-A".to_string()])]
+    ilks: Vec<String>,
+
+    /// evaluate every ilk present in the snapshots
+    #[arg(long, conflicts_with = "ilks")]
+    all_ilks: bool,
+}
+
+#[derive(Args)]
+struct WindowArgs {
+    /// pairs are built from blocks at least this many blocks apart
+    #[arg(long, default_value_t = 1)]
+    min_block_gap: u64,
+
+    /// pairs are built from blocks less than this many blocks apart
+    /// (40000 blocks = around one week)
+    #[arg(long, default_value_t = 10000)]
+    max_block_gap: u64,
+
+    /// only build pairs from blocks exactly this many blocks apart
+    #[arg(long, conflicts_with_all = ["min_block_gap", "max_block_gap"])]
+    block_gap: Option<u64>,
+
+    /// pairs are built from snapshots at least this long apart by timestamp,
+    /// e.g. 1h (default 1s)
+    #[arg(long, value_parser = parse_duration, requires = "max_duration")]
+    min_duration: Option<u64>,
+
+    /// pairs are built from snapshots less than this long apart by timestamp, e.g. 24h,
+    /// replaces the block gap window
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        conflicts_with_all = ["min_block_gap", "max_block_gap", "block_gap"]
+    )]
+    max_duration: Option<u64>,
+
+    /// only build pairs from snapshots this long apart by timestamp, e.g. 6h
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        conflicts_with_all = ["min_block_gap", "max_block_gap", "block_gap", "max_duration"]
+    )]
+    duration: Option<u64>,
+
+    /// allowed difference from --duration, e.g. 15m
+    #[arg(long, value_parser = parse_duration, requires = "duration")]
+    tolerance: Option<u64>,
+}
+
+#[derive(Args)]
+struct VaultFilterArgs {
+    /// leave out vaults with less debt
+    #[arg(long)]
+    min_debt: Option<f64>,
+
+    /// leave out vaults with less collateral
+    #[arg(long)]
+    min_collateral: Option<f64>,
+
+    /// leave out vaults with a lower safety level
+    #[arg(long)]
+    min_safety_level: Option<f64>,
+
+    /// leave out vaults whose debt * rate is below this dust limit
+    #[arg(long)]
+    dust: Option<f64>,
+
+    /// only evaluate this vault id, can be repeated
+    #[arg(long = "include-vault")]
+    include_vaults: Vec<String>,
+
+    /// leave out this vault id, can be repeated
+    #[arg(long = "exclude-vault")]
+    exclude_vaults: Vec<String>,
+
+    /// select vaults by collateral only, as before vault filters existed, which
+    /// includes vaults without debt
+    #[arg(long)]
+    legacy_vault_filter: bool,
+}
+
+#[derive(Args)]
+struct BootstrapArgs {
+    /// block bootstrap iterations for confidence intervals, off by default
+    #[arg(long = "bootstrap")]
+    iterations: Option<usize>,
+
+    /// seed of the bootstrap resampling (default 0)
+    #[arg(long)]
+    bootstrap_seed: Option<u64>,
+
+    /// length of the time blocks resampled by the bootstrap, e.g. 12h (default 1d)
+    #[arg(long, value_parser = parse_duration)]
+    bootstrap_block: Option<u64>,
+
+    /// confidence level of the bootstrap intervals (default 0.95)
+    #[arg(long)]
+    confidence: Option<f64>,
+}
+
+impl BootstrapArgs {
+    // the arguments override the config, bootstrapping is on when either sets iterations
+    fn options(&self, config: Option<&BootstrapConfig>) -> Option<BootstrapOptions> {
+        let iterations = self.iterations.or(config.map(|config| config.iterations))?;
+        let config = config.map(|config| config.options());
+        Some(BootstrapOptions {
+            iterations,
+            seed: self
+                .bootstrap_seed
+                .or(config.map(|config| config.seed))
+                .unwrap_or(0),
+            blockLength: self
+                .bootstrap_block
+                .or(config.map(|config| config.blockLength))
+                .unwrap_or(DEFAULT_BLOCK_LENGTH),
+            confidence: self
+                .confidence
+                .or(config.map(|config| config.confidence))
+                .unwrap_or(DEFAULT_CONFIDENCE),
+        })
+    }
+}
+
+#[derive(Args)]
+struct ObjectiveArgs {
+    /// mean, median, rmse (of dRatio) or asymmetric (default mean)
+    #[arg(long)]
+    objective: Option<Objective>,
+
+    /// weight of underestimated pairs (actual above estimated capital at risk) in the
+    /// asymmetric objective (default 2)
+    #[arg(long)]
+    underestimation_weight: Option<f64>,
+}
+
+#[derive(Args)]
+struct ValidationArgs {
+    /// fit on the pairs before this block and score the fit on the pairs from it on
+    #[arg(long)]
+    split_block: Option<u64>,
+
+    /// walk-forward validation over windows of this many blocks, fit on window k and
+    /// score on window k + 1
+    #[arg(long, conflicts_with = "split_block")]
+    walk_forward: Option<u64>,
+}
+
+impl ValidationArgs {
+    // the arguments override the config, validation is on when either chooses the folds
+    fn options(
+        &self,
+        objective: &ObjectiveArgs,
+        config: Option<&ValidationConfig>,
+    ) -> Option<ValidationOptions> {
+        let folds = match (self.split_block, self.walk_forward) {
+            (Some(splitBlock), _) => Folds::TrainTest { splitBlock },
+            (None, Some(windowBlocks)) => Folds::WalkForward { windowBlocks },
+            (None, None) => config?.folds()?,
+        };
+        Some(ValidationOptions {
+            folds,
+            objective: objective
+                .objective
+                .or(config.map(|config| config.objective))
+                .unwrap_or_default(),
+            underestimationWeight: objective
+                .underestimation_weight
+                .or(config.map(|config| config.underestimationWeight))
+                .unwrap_or(DEFAULT_UNDERESTIMATION_WEIGHT),
+        })
+    }
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// strategy family to search: threshold, linear or logistic
+    #[arg(long, default_value = "threshold")]
+    strategy: StrategyFamily,
+
+    /// range of a parameter as name=lower:upper, e.g. threshold=25:300, can be repeated,
+    /// parameters without one use the defaults of the family
+    #[arg(long = "bound")]
+    bounds: Vec<ParameterBound>,
+
+    /// grid, nelder-mead or random
+    #[arg(long, default_value = "grid")]
+    method: Method,
+
+    #[command(flatten)]
+    objective: ObjectiveArgs,
+
+    /// grid values per parameter
+    #[arg(long, default_value_t = 5)]
+    grid_points: usize,
+
+    /// grid rounds around the best point after the first grid
+    #[arg(long, default_value_t = 3)]
+    refinements: usize,
+
+    /// nelder-mead iterations
+    #[arg(long, default_value_t = 100)]
+    max_iterations: usize,
+
+    /// nelder-mead stops once the objective values and the scaled parameters of the
+    /// simplex are this close
+    #[arg(long, default_value_t = 1e-4)]
+    simplex_tolerance: f64,
+
+    /// random search samples
+    #[arg(long, default_value_t = 100)]
+    samples: usize,
+
+    /// seed of the random search
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+impl SearchArgs {
+    fn options(&self) -> Result<OptimizerOptions, String> {
+        Ok(OptimizerOptions {
+            family: self.strategy,
+            bounds: self.strategy.bounds(&self.bounds)?,
+            method: self.method,
+            objective: self.objective.objective.unwrap_or_default(),
+            underestimationWeight: self
+                .objective
+                .underestimation_weight
+                .unwrap_or(DEFAULT_UNDERESTIMATION_WEIGHT),
+            gridPoints: self.grid_points,
+            refinements: self.refinements,
+            maxIterations: self.max_iterations,
+            tolerance: self.simplex_tolerance,
+            samples: self.samples,
+            seed: self.seed,
+        })
+    }
+}
+
+impl VaultFilterArgs {
+    fn filter(&self) -> VaultFilter {
+        VaultFilter {
+            minDebt: self.min_debt,
+            minCollateral: self.min_collateral,
+            minSafetyLevel: self.min_safety_level,
+            dust: self.dust,
+            includeVaults: self.include_vaults.iter().cloned().collect(),
+            excludeVaults: self.exclude_vaults.iter().cloned().collect(),
+            legacy: self.legacy_vault_filter,
+        }
+    }
+}
+
+impl WindowArgs {
+    fn window(&self) -> Window {
+        if let Some(gap) = self.duration {
+            return Window::FixedTimestampGap {
+                gap,
+                tolerance: self.tolerance.unwrap_or(0),
+            };
+        }
+        if let Some(max) = self.max_duration {
+            return Window::TimestampRange {
+                min: self.min_duration.unwrap_or(1),
+                max,
+            };
+        }
+        match self.block_gap {
+            Some(gap) => Window::FixedBlockGap { gap },
+            None => Window::BlockRange {
+                min: self.min_block_gap,
+                max: self.max_block_gap,
+            },
+        }
+    }
+}
+
+impl DataArgs {
+    fn sources(&self) -> DataSources {
+        DataSources {
+            vaultHistory: self.vault_history.clone(),
+            vaultSet: self.vault_set.clone(),
+            transitions: None,
+        }
+    }
+}
+
+impl IlkArgs {
+    fn selection(&self) -> IlkSelection {
+        if self.all_ilks {
+            IlkSelection::All
+        } else {
+            IlkSelection::Only(self.ilks.clone())
+        }
+    }
+}
+
+// parsed once, so the size of the backtest arguments does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+enum Command {
+    /// Evaluate dRatio for every (threshold, coefficient) in the parameter grid
+    Backtest {
+        #[command(flatten)]
+        data: DataArgs,
+
+        #[command(flatten)]
+        ilk: IlkArgs,
+
+        #[command(flatten)]
+        window: WindowArgs,
+
+        #[command(flatten)]
+        vault_filter: VaultFilterArgs,
+
+        #[command(flatten)]
+        bootstrap: BootstrapArgs,
+
+        #[command(flatten)]
+        validation: ValidationArgs,
+
+        #[command(flatten)]
+        objective: ObjectiveArgs,
+
+        /// safety level thresholds of the grid, comma separated
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_values_t = [300.0, 200.0, 100.0, 50.0, 25.0]
+        )]
+        thresholds: Vec<f64>,
+
+        /// debt coefficients of the grid, comma separated
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_values_t = [0.5, 0.4, 0.3, 0.2]
+        )]
+        coefficients: Vec<f64>,
+
+        /// float or fixed-point, fixed-point computes the liquidation check and
+        /// the debt sums exactly in wad/ray
+        #[arg(long, default_value = "float")]
+        arithmetic: Arithmetic,
+
+        /// skip, count or fail on vaults closed at the second block of a pair or
+        /// missing from the vault history
+        #[arg(long, default_value = "count")]
+        missing_vaults: MissingVaultPolicy,
+
+        /// what a liquidated vault counts as: full-debt, liquidated-debt or
+        /// seized-collateral-value (amounts from the liquidation logs)
+        #[arg(long, default_value = "full-debt")]
+        actual_liquidation: ActualLiquidation,
+
+        /// worker threads for the evaluation, defaults to the number of cores
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// file to keep the precomputed transitions in, reused by later runs with the
+        /// same data, ilks, window and missing vault policy
+        #[arg(long)]
+        transitions: Option<PathBuf>,
+
+        /// directory to write dratio.json and dratio.csv into
+        #[arg(long)]
+        output: Option<PathBuf>,
+
+        /// JSON Lines file to write every evaluated block pair into
+        #[arg(long)]
+        trace: Option<PathBuf>,
+    },
+    /// Run the backtest described by a .toml or .json run config file
+    Run {
+        config: PathBuf,
+
+        /// float or fixed-point, overrides the config
+        #[arg(long)]
+        arithmetic: Option<Arithmetic>,
+
+        /// skip, count or fail, overrides the config
+        #[arg(long)]
+        missing_vaults: Option<MissingVaultPolicy>,
+
+        /// full-debt, liquidated-debt or seized-collateral-value, overrides the config
+        #[arg(long)]
+        actual_liquidation: Option<ActualLiquidation>,
+
+        #[command(flatten)]
+        bootstrap: BootstrapArgs,
+
+        #[command(flatten)]
+        validation: ValidationArgs,
+
+        #[command(flatten)]
+        objective: ObjectiveArgs,
+
+        /// worker threads for the evaluation, overrides the config
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// file to keep the precomputed transitions in, overrides the config
+        #[arg(long)]
+        transitions: Option<PathBuf>,
+
+        /// directory to write dratio.json and dratio.csv into, overrides the config
+        #[arg(long)]
+        output: Option<PathBuf>,
+
+        /// JSON Lines file to write every evaluated block pair into, overrides the config
+        #[arg(long)]
+        trace: Option<PathBuf>,
+    },
+    /// Search the parameters of a strategy family that minimize an objective
+    Optimize {
+        #[command(flatten)]
+        data: DataArgs,
+
+        #[command(flatten)]
+        ilk: IlkArgs,
+
+        #[command(flatten)]
+        window: WindowArgs,
+
+        #[command(flatten)]
+        vault_filter: VaultFilterArgs,
+
+        #[command(flatten)]
+        search: SearchArgs,
+
+        #[command(flatten)]
+        validation: ValidationArgs,
+
+        /// float or fixed-point
+        #[arg(long, default_value = "float")]
+        arithmetic: Arithmetic,
+
+        /// skip, count or fail
+        #[arg(long, default_value = "count")]
+        missing_vaults: MissingVaultPolicy,
+
+        /// full-debt, liquidated-debt or seized-collateral-value
+        #[arg(long, default_value = "full-debt")]
+        actual_liquidation: ActualLiquidation,
+
+        /// worker threads for the evaluation, defaults to the number of cores
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// file to keep the precomputed transitions in
+        #[arg(long)]
+        transitions: Option<PathBuf>,
+
+        /// directory to write optimize.json, search.csv and validation.json into
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Print a summary of the vault set at a block
+    Inspect {
+        #[arg(long, default_value = "../subgraph-tools/data/vaultSet")]
+        vault_set: String,
+
+        block: String,
+
+        /// only show this ilk
+        #[arg(long)]
+        ilk: Option<String>,
+    },
+    /// Check that all data files parse and reference each other consistently
+    Validate {
+        #[command(flatten)]
+        data: DataArgs,
+    },
+    /// List blocks in the vault set directory in ascending order
+    ListBlocks {
+        #[arg(long, default_value = "../subgraph-tools/data/vaultSet")]
+        vault_set: String,
+    },
+    /// Manage the binary snapshot cache of the vault set directory
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Parse every vault set into <vault-set>.cache, which is then read instead of the json
+    /// files that did not change since
+    Build {
+        #[arg(long, default_value = "../subgraph-tools/data/vaultSet")]
+        vault_set: String,
+    },
+}
 
-    // Read the JSON contents of the file as an instance of `User`.
-    let u = serde_json::from_reader(reader)?;
+fn print_report(report: &DRatioReport) {
     println!(
-        "Time elapsed in read_vault_history_from_file() is: {:?}",
+        "ilk: {}, dRatioMedian: {}, plusSum({}) + minusSum({}) = dRatio({}), plusCount({}) + minusCount({}) = validDataPointCount({}), d ratio mean: {}",
+        report.ilk.as_deref().unwrap_or("all"),
+        report.dRatioMedian,
+        report.plusSum,
+        report.minusSum,
+        report.dRatio,
+        report.plusCount,
+        report.minusCount,
+        report.validDataPointCount,
+        report.dRatioMean,
+    );
+    let statistics = &report.dRatioStatistics;
+    if let Some(percentiles) = &statistics.percentiles {
+        println!(
+            "ilk: {}, signed dRatio: min {}, p5 {}, p25 {}, p50 {}, p75 {}, p95 {}, max {}, std dev {}, mae {}, rmse {}, bias {}",
+            report.ilk.as_deref().unwrap_or("all"),
+            statistics.min.unwrap_or(f64::NAN),
+            percentiles.p5,
+            percentiles.p25,
+            percentiles.p50,
+            percentiles.p75,
+            percentiles.p95,
+            statistics.max.unwrap_or(f64::NAN),
+            statistics.stdDev.unwrap_or(f64::NAN),
+            statistics.mae.unwrap_or(f64::NAN),
+            statistics.rmse.unwrap_or(f64::NAN),
+            statistics.bias.unwrap_or(f64::NAN),
+        );
+    }
+    if let Some(bootstrap) = &report.bootstrap {
+        println!(
+            "ilk: {}, {}% bootstrap intervals ({} iterations, {} blocks): d ratio mean [{}, {}], dRatioMedian [{}, {}], plusCount [{}, {}], minusCount [{}, {}]",
+            report.ilk.as_deref().unwrap_or("all"),
+            bootstrap.confidence * 100.0,
+            bootstrap.iterations,
+            bootstrap.blockCount,
+            bootstrap.dRatioMean.lower,
+            bootstrap.dRatioMean.upper,
+            bootstrap.dRatioMedian.lower,
+            bootstrap.dRatioMedian.upper,
+            bootstrap.plusCount.lower,
+            bootstrap.plusCount.upper,
+            bootstrap.minusCount.lower,
+            bootstrap.minusCount.upper,
+        );
+    }
+    if let (Some(closed), Some(opened), Some(missingHistory)) = (
+        report.closedVaultCount,
+        report.openedVaultCount,
+        report.missingHistoryVaultCount,
+    ) {
+        println!(
+            "ilk: {}, closed vaults: {}, opened vaults: {}, vaults missing from history: {}",
+            report.ilk.as_deref().unwrap_or("all"),
+            closed,
+            opened,
+            missingHistory,
+        );
+    }
+}
+
+fn print_validation(report: &ValidationReport) {
+    for foldReport in &report.foldReports {
+        let fold = &foldReport.fold;
+        println!(
+            "fold: {}, train blocks: {}..{} ({} pairs), test blocks: {}..{} ({} pairs), fitted: {}, train objective: {}, test objective: {}",
+            fold.index,
+            fold.train.start,
+            fold.train.end,
+            foldReport.trainPairCount,
+            fold.test.start,
+            fold.test.end,
+            foldReport.testPairCount,
+            foldReport.strategy.as_deref().unwrap_or("none"),
+            foldReport.trainObjective.unwrap_or(f64::NAN),
+            foldReport.testObjective.unwrap_or(f64::NAN),
+        );
+        if let Some(test) = &foldReport.test {
+            for ilkReport in test.ilks.iter().chain([&test.aggregate]) {
+                print_report(ilkReport);
+            }
+        }
+    }
+    if report.foldReports.is_empty() {
+        println!("no folds, the pairs do not cover more than one window");
+    }
+    println!(
+        "mean test objective over {} folds: {}",
+        report
+            .foldReports
+            .iter()
+            .filter(|foldReport| foldReport.testObjective.is_some())
+            .count(),
+        report.meanTestObjective.unwrap_or(f64::NAN),
+    );
+}
+
+type ReadDir = fn(&str, &mut VaultSetsByBlock) -> Result<CacheUsage, error::Error>;
+
+// `read` is read_dir, or read_json_dir to bypass the snapshot cache
+fn load(
+    vault_history: &Path,
+    vault_set: &str,
+    read: ReadDir,
+) -> Result<(LiquidationIndex, VaultSetsByBlock), Box<dyn Error>> {
+    let start = Instant::now();
+    let liquidationTimestampListByVault = read_liquidation_index_from_file(vault_history)?;
+    println!(
+        "Time elapsed in read_liquidation_index_from_file() is: {:?}",
         start.elapsed()
     );
-    Ok(u)
+
+    let start = Instant::now();
+    let mut allVaultsAtBlock: VaultSetsByBlock = HashMap::new();
+    let usage = read(vault_set, &mut allVaultsAtBlock)?;
+    if let Some(e) = &usage.ignored {
+        println!("ignoring snapshot cache: {}", e);
+    }
+    if let Some(cachePath) = &usage.cachePath {
+        println!(
+            "{} of {} blocks read from {}",
+            usage.cachedBlocks,
+            usage.blocks,
+            cachePath.display()
+        );
+    }
+    println!("Time elapsed in read_dir() is: {:?}", start.elapsed());
+    Ok((liquidationTimestampListByVault, allVaultsAtBlock))
+}
+
+fn sorted_blocks(allVaultsAtBlock: &VaultSetsByBlock) -> Vec<&String> {
+    let mut blocks: Vec<&String> = allVaultsAtBlock.keys().collect();
+    blocks.sort_by_key(|block| (block.parse::<u64>().unwrap_or(u64::MAX), block.to_string()));
+    blocks
 }
 
-fn read_vault_set_from_file<P: AsRef<Path>>(
-    path: P,
-) -> Result<HashMap<String, VaultSet>, Box<dyn Error>> {
-    // Open the file in read-only mode with buffer.
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-
-    // Read the JSON contents of the file as an instance of `User`.
-    let u = serde_json::from_reader(reader);
-    match u {
-        Ok(data) => Ok(data),
-        Err(e) => {
-            println!("error reading json file: {}", e.to_string());
-            return Err(Box::new(e));
+// reads the transitions from `data.transitions` when it was built with the same settings,
+// otherwise loads the data, builds them and writes them there
+fn load_transitions(
+    data: &DataSources,
+    ilkSelection: &IlkSelection,
+    window: &Window,
+    options: &EvaluationOptions,
+) -> Result<Vec<IlkTransitions>, Box<dyn Error>> {
+    let key = TransitionCacheKey {
+        version: TRANSITION_CACHE_VERSION,
+        vaultHistory: data.vaultHistory.clone(),
+        vaultSet: data.vaultSet.clone(),
+        vaultHistoryStamp: FileStamp::of(&data.vaultHistory)?,
+        blockStamps: block_stamps(&data.vaultSet)?,
+        ilkSelection: ilkSelection.clone(),
+        window: *window,
+        missingVaultPolicy: options.missingVaultPolicy,
+        vaultFilter: options.vaultFilter.clone(),
+    };
+    if let Some(path) = data.transitions.as_deref().filter(|path| path.exists()) {
+        match read_transition_cache(path) {
+            Ok(cache) if cache.key == key && key.is_stamped() => {
+                println!("transitions read from {}", path.display());
+                return Ok(cache.ilkTransitions);
+            }
+            Ok(_) => println!(
+                "{} was built with other settings or data, rebuilding transitions",
+                path.display()
+            ),
+            Err(e) => println!("ignoring transitions: {}, rebuilding them", e),
         }
     }
+
+    let (liquidationTimestampListByVault, allVaultsAtBlock) =
+        load(&data.vaultHistory, &data.vaultSet, read_dir)?;
+    println!("blocks_count: {}", allVaultsAtBlock.len());
+
+    let start = Instant::now();
+    let blockIndex = BlockIndex::new(&allVaultsAtBlock);
+    let ilks = ilkSelection.resolve(blockIndex.snapshots())?;
+    let dataset = blockIndex.pairs(window);
+    println!(
+        "Time elapsed in preparing dataset is: {:?}",
+        start.elapsed()
+    );
+
+    println!("dataset length: {}", dataset.len());
+    if dataset.is_empty() {
+        println!("no pairs in the window, the reports have no data points");
+    }
+
+    let start = Instant::now();
+    let ilkTransitions =
+        build_ilk_transitions(&dataset, &liquidationTimestampListByVault, &ilks, options)?;
+    println!(
+        "Time elapsed in building transitions is: {:?}",
+        start.elapsed()
+    );
+    if let Some(path) = &data.transitions {
+        let cache = TransitionCache {
+            key,
+            ilkTransitions,
+        };
+        write_transition_cache(path, &cache)?;
+        println!("transitions written to {}", path.display());
+        return Ok(cache.ilkTransitions);
+    }
+    Ok(ilkTransitions)
 }
 
-pub fn read_dir(
-    path: &str,
-    allVaultsAtBlock: &mut HashMap<String, HashMap<String, VaultSet>>,
+// the backtest commands hand their settings through one by one
+#[allow(clippy::too_many_arguments)]
+fn backtest(
+    data: &DataSources,
+    ilkSelection: &IlkSelection,
+    window: &Window,
+    strategies: &[Box<dyn LiquidationStrategy>],
+    options: &EvaluationOptions,
+    validation: Option<&ValidationOptions>,
+    output: Option<&Path>,
+    trace: Option<&Path>,
 ) -> Result<(), Box<dyn Error>> {
-    let start = Instant::now();
-    let dir = fs::read_dir(path)?;
-    // take 10 is for debug
-    for item in dir.into_iter()
-    // .take(10)
-    {
-        let item = item?;
-        let block_number = item.file_name().into_string();
-        let block_number_path = item.path();
-        match block_number {
-            Ok(block_number_str) => {
-                let inner_dir = fs::read_dir(block_number_path)?;
-                let mut json_file: Option<PathBuf> = None;
-                for inner_item in inner_dir.into_iter() {
-                    json_file = Some(inner_item?.path());
-                }
-                match json_file {
-                    Some(x) => {
-                        let vault_set = read_vault_set_from_file(x)?;
-                        allVaultsAtBlock.insert(block_number_str, vault_set);
-                    }
-                    None => {}
+    window.validate()?;
+    if let Some(bootstrap) = &options.bootstrap {
+        bootstrap.validate()?;
+    }
+    if let Some(validation) = validation {
+        validation.validate()?;
+    }
+    let ilkTransitions = load_transitions(data, ilkSelection, window, options)?;
+
+    let mut traceWriter = trace.map(TraceWriter::create).transpose()?;
+    let mut traceError: Option<Box<dyn Error>> = None;
+    let mut writeTrace = |dataPointTrace: DataPointTrace| {
+        if let Some(writer) = traceWriter.as_mut() {
+            if traceError.is_none() {
+                if let Err(e) = writer.write(&dataPointTrace) {
+                    traceError = Some(e.into());
                 }
             }
-            Err(e) => {
-                println!("error: {}", e.to_str().unwrap())
-            }
         }
+    };
+    let start = Instant::now();
+    let reports = evaluate_strategies(
+        &ilkTransitions,
+        strategies,
+        options,
+        if trace.is_some() {
+            Some(&mut writeTrace)
+        } else {
+            None
+        },
+    )?;
+    if let Some(e) = traceError {
+        return Err(e);
+    }
+    for (strategy, report) in strategies.iter().zip(&reports) {
+        println!("parameters: {}", strategy.label());
+        for ilkReport in report.ilks.iter().chain([&report.aggregate]) {
+            print_report(ilkReport);
+        }
+    }
+    println!(
+        "Time elapsed in calculating dRatio is: {:?}",
+        start.elapsed()
+    );
+    if let Some(output) = output {
+        write_reports(output, &reports)?;
+        println!("reports written to {}", output.display());
+    }
+    if let Some(validation) = validation {
+        // every fold picks the strategy of the grid that fits its train pairs best
+        let mut fit = |train: &[IlkTransitions]| {
+            fit_best(train, strategies, options, validation).map(|best| {
+                best.map(|index| {
+                    Box::new(strategies[index].as_ref()) as Box<dyn LiquidationStrategy>
+                })
+            })
+        };
+        let report = cross_validate(&ilkTransitions, options, validation, &mut fit)?;
+        print_validation(&report);
+        if let Some(output) = output {
+            write_validation(output, &report)?;
+            println!("validation written to {}", output.display());
+        }
+    }
+    if let (Some(writer), Some(trace)) = (traceWriter, trace) {
+        writer.finish()?;
+        println!("trace written to {}", trace.display());
     }
-    println!("Time elapsed in read_dir() is: {:?}", start.elapsed());
     Ok(())
 }
 
-fn main() {
-    let vaults =
-        read_vault_history_from_file("../subgraph-tools/data/jsons/vaultHistory.json").unwrap();
-    let vault_ids = vaults.keys();
-    let mut liquidationTimestampListByVault: HashMap<String, Vec<u64>> = HashMap::new();
-    for vault_id in vault_ids.into_iter() {
-        let timestamp: Vec<u64> = vaults[vault_id].vaults[0]
-            .logs
-            .iter()
-            .filter(|vaultLog| vaultLog.__typename == "liquidationStartLog")
-            .filter_map(|vaultLog| vaultLog.timestamp.parse::<u64>().ok())
-            .collect();
-        liquidationTimestampListByVault.insert(vault_id.clone(), timestamp);
-    }
-
-    let mut allVaultsAtBlock: HashMap<String, HashMap<String, VaultSet>> = HashMap::new();
-    // list up directories
-    let read_dir_result = read_dir("../subgraph-tools/data/vaultSet", &mut allVaultsAtBlock);
-    // now allVaultsAtBlock contains data
-    if read_dir_result.is_ok() {
-        let blocks_keys: Vec<String> = allVaultsAtBlock
-            .keys()
-            .into_iter()
-            .map(|v| v.to_string())
-            .collect();
-        // let mut blocks_keys_2 = blocks_keys;
-        let blocks_count = blocks_keys.len();
+fn run_optimizer(
+    data: &DataSources,
+    ilkSelection: &IlkSelection,
+    window: &Window,
+    options: &EvaluationOptions,
+    optimizerOptions: &OptimizerOptions,
+    validation: Option<&ValidationOptions>,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    window.validate()?;
+    optimizerOptions.validate()?;
+    if let Some(validation) = validation {
+        validation.validate()?;
+    }
+    let ilkTransitions = load_transitions(data, ilkSelection, window, options)?;
+
+    let start = Instant::now();
+    let Some(result) = optimize(&ilkTransitions, options, optimizerOptions)? else {
+        return Err("no parameters within the bounds give valid data points".into());
+    };
+    for step in &result.trace {
         println!(
-            "blocks_count: {}, blocks_keys has 16266198: {}",
-            blocks_count,
-            blocks_keys.contains(&("16266198").to_string()),
+            "evaluation: {}, round: {}, parameters: {}, objective: {}, d ratio mean: {}, dRatioMedian: {}, validDataPointCount: {}",
+            step.evaluation,
+            step.round,
+            step.strategy,
+            step.objective,
+            step.dRatioMean,
+            step.dRatioMedian,
+            step.validDataPointCount,
         );
+    }
+    println!(
+        "best after {} evaluations: {}, objective: {}",
+        result.trace.len(),
+        result.best.strategy,
+        result.best.objective,
+    );
+    for ilkReport in result
+        .bestReport
+        .ilks
+        .iter()
+        .chain([&result.bestReport.aggregate])
+    {
+        print_report(ilkReport);
+    }
+    println!("Time elapsed in the search is: {:?}", start.elapsed());
+    if let Some(output) = output {
+        write_search(output, &result)?;
+        println!("search written to {}", output.display());
+    }
+    if let Some(validation) = validation {
+        // every fold runs the search on its train pairs only
+        let mut fit = |train: &[IlkTransitions]| {
+            optimize(train, options, optimizerOptions).map(|result| {
+                result.and_then(|result| optimizerOptions.family.strategy(&result.best.parameters))
+            })
+        };
+        let report = cross_validate(&ilkTransitions, options, validation, &mut fit)?;
+        print_validation(&report);
+        if let Some(output) = output {
+            write_validation(output, &report)?;
+            println!("validation written to {}", output.display());
+        }
+    }
+    Ok(())
+}
 
-        let start = Instant::now();
-        let mut dataset: Vec<Data> = vec![];
-        for block_key_1_index in 0..blocks_count {
-            let block_key_1 = &blocks_keys[block_key_1_index];
-            let vaultsAtFirstBlock = &allVaultsAtBlock[block_key_1];
-            for block_key_2_index in 0..blocks_count {
-                let block_key_2 = &blocks_keys[block_key_2_index];
-                let first_block = block_key_1.parse::<u64>();
-                let second_block = block_key_2.parse::<u64>();
-                match (first_block, second_block) {
-                    (Ok(first_block_num), Ok(second_block_num)) => {
-                        if first_block_num < second_block_num
-                            && second_block_num - first_block_num < 10000
-                        // 40000 blocks = around one week
-                        {
-                            let vaultsAtSecondBlock = &allVaultsAtBlock[block_key_2];
-                            let data: Data = Data {
-                                firstBlock: block_key_1.to_string(),
-                                secondBlock: block_key_2.to_string(),
-                                vaultsAtFirstBlock: vaultsAtFirstBlock,
-                                vaultsAtSecondBlock: vaultsAtSecondBlock,
-                            };
-                            dataset.push(data);
-                        }
-                    }
-                    _ => {}
-                }
+fn inspect(vault_set: &str, block: &str, ilk: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(vault_set).join(block);
+    let Some(vaultsAtBlock) = read_block_dir(&path)? else {
+        return Err(format!("no vault set file in {}", path.display()).into());
+    };
+    if let Some(ilk) = ilk {
+        if !vaultsAtBlock.contains_key(ilk) {
+            return Err(error::Error::MissingIlk {
+                ilk: ilk.to_string(),
+                block: Some(block.to_string()),
             }
+            .into());
         }
+    }
+    let mut ilks: Vec<&String> = vaultsAtBlock.keys().collect();
+    ilks.sort();
+    for name in ilks {
+        if ilk.is_some_and(|ilk| ilk != name) {
+            continue;
+        }
+        let vaultSet = &vaultsAtBlock[name];
+        let debtSum: f64 = vaultSet.resultArray.iter().map(|vault| vault.debt.0).sum();
+        let collateralSum: f64 = vaultSet
+            .resultArray
+            .iter()
+            .map(|vault| vault.collateral.0)
+            .sum();
         println!(
-            "Time elapsed in preparing dataset is: {:?}",
-            start.elapsed()
+            "ilk: {}, timestamp: {}, price: {}, rate: {}, liquidationRatio: {}, vaults: {}, collateralSum: {}, debtSum: {}",
+            name,
+            vaultSet.timestamp.0,
+            vaultSet.price.0,
+            vaultSet.rate.0,
+            vaultSet.liquidationRatio.0,
+            vaultSet.resultArray.len(),
+            collateralSum,
+            debtSum,
         );
+    }
+    Ok(())
+}
 
-        println!("dataset length: {}", dataset.len());
-
-        let parameters: Vec<(f64, f64)> = vec![
-            (300.0, 0.5),
-            (200.0, 0.5),
-            (100.0, 0.5),
-            (50.0, 0.5),
-            (25.0, 0.5),
-            (300.0, 0.4),
-            (200.0, 0.4),
-            (100.0, 0.4),
-            (50.0, 0.4),
-            (25.0, 0.4),
-            (300.0, 0.3),
-            (200.0, 0.3),
-            (100.0, 0.3),
-            (50.0, 0.3),
-            (25.0, 0.3),
-            (300.0, 0.2),
-            (200.0, 0.2),
-            (100.0, 0.2),
-            (50.0, 0.2),
-            (25.0, 0.2),
-        ];
-        for (threshold, coefficient) in parameters {
-            println!(
-                "parameters: threshold: {}, coefficient: {}",
-                threshold, coefficient
-            );
-            let start = Instant::now();
-            let mut dRatio: f64 = 0.0;
-            let mut dRatioList: Vec<f64> = vec![];
-            let mut validDataPointCount: u32 = 0;
-            let mut plusCount: u32 = 0;
-            let mut plusSum: f64 = 0.0;
-            let mut minusCount: u32 = 0;
-            let mut minusSum: f64 = 0.0;
-
-            for index in 0..dataset.len() {
-                let row = &dataset[index];
-
-                let firstBlock = &row.firstBlock;
-                let firstTimestamp = &row.vaultsAtFirstBlock["ETH-A"].timestamp;
-                let firstVaults = &row.vaultsAtFirstBlock["ETH-A"].resultArray;
-                let firstPrice = &row.vaultsAtFirstBlock["ETH-A"].price;
-                let firstRate = &row.vaultsAtFirstBlock["ETH-A"].rate;
-                let firstLiquidationRatio = &row.vaultsAtFirstBlock["ETH-A"].liquidationRatio;
-
-                let secondBlock = &row.secondBlock;
-                let secondTimestamp = &row.vaultsAtSecondBlock["ETH-A"].timestamp;
-                let secondVaults = &row.vaultsAtSecondBlock["ETH-A"].resultArray;
-                let secondPrice = &row.vaultsAtSecondBlock["ETH-A"].price;
-                let secondRate = &row.vaultsAtSecondBlock["ETH-A"].rate;
-                let secondLiquidationRatio = &row.vaultsAtSecondBlock["ETH-A"].liquidationRatio;
-
-                let blockDiffMetadata: BlockDiffMetadata = BlockDiffMetadata {
-                    firstBlock: firstBlock.to_string(),
-                    firstPrice: firstPrice.0.to_string(),
-                    firstRate: firstRate.to_string(),
-                    firstLiquidationRatio: firstLiquidationRatio.to_string(),
-                    firstTimestamp: firstTimestamp.to_string(),
-                    secondBlock: secondBlock.to_string(),
-                    secondPrice: secondPrice.0.to_string(),
-                    secondRate: secondRate.to_string(),
-                    secondLiquidationRatio: secondLiquidationRatio.to_string(),
-                    secondTimestamp: secondTimestamp.to_string(),
-                };
-
-                let mut vaultTransition: HashMap<&String, VaultTransitionInnerType> =
-                    HashMap::new();
-
-                let mut secondvaultsById: HashMap<&String, &SubgraphVault> = HashMap::new();
-                for i in 0..secondVaults.len() {
-                    secondvaultsById.insert(&secondVaults[i].id, &secondVaults[i]);
+fn validate(data: &DataArgs) -> Result<(), Box<dyn Error>> {
+    let (liquidationTimestampListByVault, allVaultsAtBlock) =
+        load(&data.vault_history, &data.vault_set, read_json_dir)?;
+    let mut problems: u32 = 0;
+    for block in sorted_blocks(&allVaultsAtBlock) {
+        let vaultsAtBlock = &allVaultsAtBlock[block];
+        if block.parse::<u64>().is_err() {
+            println!("{}: directory name is not a block number", block);
+            problems += 1;
+        }
+        let mut ilks: Vec<&String> = vaultsAtBlock.keys().collect();
+        ilks.sort();
+        for ilk in ilks {
+            let vaultSet = &vaultsAtBlock[ilk];
+            // malformed numbers are rejected while loading, only check the range here
+            for (field, value) in [
+                ("price", vaultSet.price.0),
+                ("rate", vaultSet.rate.0),
+                ("liquidationRatio", vaultSet.liquidationRatio.0),
+            ] {
+                if !value.is_finite() || value <= 0.0 {
+                    println!("{} {}: invalid {} {}", block, ilk, field, value);
+                    problems += 1;
                 }
-
-                for j in 0..firstVaults.len() {
-                    let vault = &firstVaults[j];
-                    let collateral = vault.collateral.parse::<f64>();
-                    let debt = vault.collateral.parse::<f64>();
-                    match (collateral, debt) {
-                        (Ok(collateral), Ok(debt)) => {
-                            if collateral > 0.0 && debt > 0.0 {
-                                let liquidationTimestampList =
-                                    &liquidationTimestampListByVault[&vault.id];
-                                let liquidationTimestampAny =
-                                    liquidationTimestampList
-                                        .iter()
-                                        .find(|liquidationTimestamp| {
-                                            let firstTimestampU64 = firstTimestamp.parse::<u64>();
-                                            let secondTimestampU64 = secondTimestamp.parse::<u64>();
-                                            return match (firstTimestampU64, secondTimestampU64) {
-                                                (Ok(firstTimestampU64), Ok(secondTimestampU64)) => {
-                                                    return &&firstTimestampU64
-                                                        < liquidationTimestamp
-                                                        && liquidationTimestamp
-                                                            < &&secondTimestampU64;
-                                                }
-                                                _ => false,
-                                            };
-                                        });
-                                vaultTransition.insert(
-                                    &vault.id,
-                                    match liquidationTimestampAny {
-                                        Some(liquidationTimestamp) => VaultTransitionInnerType {
-                                            first: vault,
-                                            second: secondvaultsById[&vault.id],
-                                            liquidated: true,
-                                            liquidationTimestamp: Some(*liquidationTimestamp),
-                                        },
-                                        None => VaultTransitionInnerType {
-                                            first: vault,
-                                            second: secondvaultsById[&vault.id],
-                                            liquidated: false,
-                                            liquidationTimestamp: None,
-                                        },
-                                    },
-                                );
-                            }
-                        }
-                        _ => {}
+            }
+            for vault in &vaultSet.resultArray {
+                for (field, value) in [
+                    ("collateral", vault.collateral.0),
+                    ("debt", vault.debt.0),
+                    ("safetyLevel", vault.safetyLevel.0),
+                ] {
+                    if !value.is_finite() || value < 0.0 {
+                        println!(
+                            "{} {} {}: invalid {} {}",
+                            block, ilk, vault.id, field, value
+                        );
+                        problems += 1;
                     }
                 }
+                if !liquidationTimestampListByVault.contains(&vault.id) {
+                    println!("{} {} {}: missing from vault history", block, ilk, vault.id);
+                    problems += 1;
+                }
+            }
+        }
+    }
+    println!(
+        "blocks: {}, vaults in history: {}, problems: {}",
+        allVaultsAtBlock.len(),
+        liquidationTimestampListByVault.len(),
+        problems
+    );
+    if problems > 0 {
+        return Err(format!("{} problems found", problems).into());
+    }
+    Ok(())
+}
 
-                let vaultTransitionWithMetadata: VaultTransitionWithMetadata =
-                    VaultTransitionWithMetadata {
-                        meta: blockDiffMetadata,
-                        vaultTransition: vaultTransition,
-                    };
-
-                let strategy =
-                    |safetyLevel: f64, debt: f64, threshold: f64, coefficient: f64| -> f64 {
-                        if safetyLevel > threshold {
-                            return debt * coefficient;
-                        } else {
-                            return debt;
-                        }
-                    };
-
-                // calculate data from vaultTransitionSet
-                let secondPrice = vaultTransitionWithMetadata.meta.secondPrice.parse::<f64>();
-                let firstPrice = vaultTransitionWithMetadata.meta.firstPrice.parse::<f64>();
-                match (firstPrice, secondPrice) {
-                    (Ok(firstPrice), Ok(secondPrice)) => {
-                        let price_drop_ratio = secondPrice / firstPrice;
-                        if price_drop_ratio < 1.0 {
-                            // calculated capital at risk value
-                            let capitalAtRiskValueRisk = vaultTransitionWithMetadata
-                                .vaultTransition
-                                .values()
-                                .into_iter()
-                                .map(|vaultTransitionInner| {
-                                    let first = vaultTransitionInner.first;
-                                    match (
-                                        first.collateral.parse::<f64>(),
-                                        first.debt.parse::<f64>(),
-                                        vaultTransitionWithMetadata
-                                            .meta
-                                            .firstLiquidationRatio
-                                            .parse::<f64>(),
-                                        vaultTransitionWithMetadata.meta.firstRate.parse::<f64>(),
-                                        first.safetyLevel.parse::<f64>(),
-                                    ) {
-                                        (
-                                            Ok(collateral),
-                                            Ok(debt),
-                                            Ok(liquidationRatio),
-                                            Ok(rate),
-                                            Ok(safetyLevel),
-                                        ) => {
-                                            if collateral * secondPrice
-                                                > debt * liquidationRatio * rate
-                                            {
-                                                return 0.0;
-                                            } else {
-                                                return strategy(
-                                                    safetyLevel,
-                                                    debt,
-                                                    threshold,
-                                                    coefficient,
-                                                );
-                                            }
-                                        }
-                                        _ => {
-                                            return 0.0;
-                                        }
-                                    }
-                                })
-                                .fold(0.0, |x, y| x + y);
-
-                            // actual capital at risk value
-                            let capitalAtRiskValueLiq = vaultTransitionWithMetadata
-                                .vaultTransition
-                                .values()
-                                .into_iter()
-                                .map(|vaultTransitionInner| {
-                                    let liquidatedAmount = if vaultTransitionInner.liquidated {
-                                        match (&vaultTransitionInner.first.debt).parse::<f64>() {
-                                            Ok(debt) => debt,
-                                            _ => 0.0,
-                                        }
-                                    } else {
-                                        0.0
-                                    };
-
-                                    return liquidatedAmount;
-                                })
-                                .fold(0.0, |x, y| x + y);
-                            
-                            // sum of all debt
-                            let debtSum = vaultTransitionWithMetadata
-                                .vaultTransition
-                                .values()
-                                .into_iter()
-                                .map(|vaultTransitionInner| {
-                                    match (&vaultTransitionInner.first.debt).parse::<f64>() {
-                                        Ok(debt) => debt,
-                                        _ => 0.0,
-                                    }
-                                })
-                                .fold(0.0, |x, y| x + y);
-
-                            // only think in case estimated risk is above zero. otherwise, the data point is invalid.
-                            if capitalAtRiskValueRisk > 0.0 {
-                                let maybeNan = (capitalAtRiskValueLiq - capitalAtRiskValueRisk)/debtSum;
-                                if maybeNan.is_nan() {
-                                    println!(
-                                        "nan detected: {}, {}, {}, {}",
-                                        // serde_json::to_string(vaultTransition).unwrap()
-                                        vaultTransitionWithMetadata.meta.firstLiquidationRatio,
-                                        vaultTransitionWithMetadata.meta.firstRate,
-                                        capitalAtRiskValueLiq,
-                                        capitalAtRiskValueRisk,
-                                    );
-                                } else {
-                                    dRatio += maybeNan.abs();
-                                    if capitalAtRiskValueLiq > capitalAtRiskValueRisk {
-                                        plusCount += 1;
-                                        plusSum += maybeNan;
-                                    } else {
-                                        minusCount += 1;
-                                        minusSum += maybeNan;
-                                    }
-                                    validDataPointCount += 1;
-                                    dRatioList.push(maybeNan.abs());
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
+fn list_blocks(vault_set: &str) -> Result<(), Box<dyn Error>> {
+    let mut allVaultsAtBlock: VaultSetsByBlock = HashMap::new();
+    // stdout only holds the listing
+    if let Some(e) = read_dir(vault_set, &mut allVaultsAtBlock)?.ignored {
+        eprintln!("ignoring snapshot cache: {}", e);
+    }
+    for block in sorted_blocks(&allVaultsAtBlock) {
+        let vaultsAtBlock = &allVaultsAtBlock[block];
+        let mut ilks: Vec<&String> = vaultsAtBlock.keys().collect();
+        ilks.sort();
+        let timestamp = ilks.first().map_or(String::new(), |ilk| {
+            vaultsAtBlock[*ilk].timestamp.0.to_string()
+        });
+        let ilks: Vec<&str> = ilks.iter().map(|ilk| ilk.as_str()).collect();
+        println!("{}\t{}\t{}", block, timestamp, ilks.join(","));
+    }
+    Ok(())
+}
+
+fn build_cache(vault_set: &str) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    let (path, blocks) = build_snapshot_cache(vault_set)?;
+    println!("{} blocks written to {}", blocks, path.display());
+    println!(
+        "Time elapsed in building the cache is: {:?}",
+        start.elapsed()
+    );
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Backtest {
+            data,
+            ilk,
+            window,
+            vault_filter,
+            bootstrap,
+            validation,
+            objective,
+            thresholds,
+            coefficients,
+            arithmetic,
+            missing_vaults,
+            actual_liquidation,
+            threads,
+            transitions,
+            output,
+            trace,
+        } => {
+            let mut strategies: Vec<Box<dyn LiquidationStrategy>> = vec![];
+            for coefficient in coefficients {
+                for threshold in thresholds {
+                    strategies.push(Box::new(ThresholdStrategy {
+                        threshold: *threshold,
+                        coefficient: *coefficient,
+                    }));
                 }
             }
-            // can't use sort, workaround from https://yiskw713.hatenablog.com/entry/2021/06/09/075419
-            dRatioList.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            let dRatioMedian = dRatioList[dRatioList.len() / 2];
-            let dRatioMean = dRatio / (validDataPointCount as f64) * 100.0;
-            println!(
-                "dRatioMedian: {}, plusSum({}) + minusSum({}) = dRatio({}), plusCount({}) + minusCount({}) = validDataPointCount({}), d ratio mean: {}",
-                dRatioMedian, plusSum, minusSum, dRatio, plusCount, minusCount, validDataPointCount, dRatioMean, 
-            );
-            println!(
-                "Time elapsed in calculating dRatio is: {:?}",
-                start.elapsed()
-            );
+            init_thread_pool(*threads)
+                .map_err(|e| e.into())
+                .and_then(|_| {
+                    backtest(
+                        &DataSources {
+                            transitions: transitions.clone(),
+                            ..data.sources()
+                        },
+                        &ilk.selection(),
+                        &window.window(),
+                        &strategies,
+                        &EvaluationOptions {
+                            arithmetic: *arithmetic,
+                            missingVaultPolicy: *missing_vaults,
+                            actualLiquidation: *actual_liquidation,
+                            vaultFilter: vault_filter.filter(),
+                            bootstrap: bootstrap.options(None),
+                        },
+                        validation.options(objective, None).as_ref(),
+                        output.as_deref(),
+                        trace.as_deref(),
+                    )
+                })
         }
+        Command::Run {
+            config,
+            arithmetic,
+            missing_vaults,
+            actual_liquidation,
+            bootstrap,
+            validation,
+            objective,
+            threads,
+            transitions,
+            output,
+            trace,
+        } => read_run_config(config)
+            .map_err(|e| e.into())
+            .and_then(|config| {
+                init_thread_pool(threads.or(config.threads))?;
+                let window = config.window.window()?;
+                backtest(
+                    &DataSources {
+                        transitions: transitions.clone().or(config.data.transitions.clone()),
+                        ..config.data.clone()
+                    },
+                    &config.ilks.selection(),
+                    &window,
+                    &config.strategies(),
+                    &EvaluationOptions {
+                        arithmetic: arithmetic.unwrap_or(config.arithmetic),
+                        missingVaultPolicy: missing_vaults.unwrap_or(config.missingVaults),
+                        actualLiquidation: actual_liquidation.unwrap_or(config.actualLiquidation),
+                        vaultFilter: config.vaultFilter.clone(),
+                        bootstrap: bootstrap.options(config.bootstrap.as_ref()),
+                    },
+                    validation
+                        .options(objective, config.validation.as_ref())
+                        .as_ref(),
+                    output.as_deref().or(config.output.as_deref()),
+                    trace.as_deref().or(config.trace.as_deref()),
+                )
+            }),
+        Command::Optimize {
+            data,
+            ilk,
+            window,
+            vault_filter,
+            search,
+            validation,
+            arithmetic,
+            missing_vaults,
+            actual_liquidation,
+            threads,
+            transitions,
+            output,
+        } => search
+            .options()
+            .map_err(|e| e.into())
+            .and_then(|optimizerOptions| {
+                init_thread_pool(*threads)?;
+                run_optimizer(
+                    &DataSources {
+                        transitions: transitions.clone(),
+                        ..data.sources()
+                    },
+                    &ilk.selection(),
+                    &window.window(),
+                    &EvaluationOptions {
+                        arithmetic: *arithmetic,
+                        missingVaultPolicy: *missing_vaults,
+                        actualLiquidation: *actual_liquidation,
+                        vaultFilter: vault_filter.filter(),
+                        bootstrap: None,
+                    },
+                    &optimizerOptions,
+                    validation.options(&search.objective, None).as_ref(),
+                    output.as_deref(),
+                )
+            }),
+        Command::Inspect {
+            vault_set,
+            block,
+            ilk,
+        } => inspect(vault_set, block, ilk.as_deref()),
+        Command::Validate { data } => validate(data),
+        Command::ListBlocks { vault_set } => list_blocks(vault_set),
+        Command::Cache {
+            command: CacheCommand::Build { vault_set },
+        } => build_cache(vault_set),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
     }
 }
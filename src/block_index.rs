@@ -0,0 +1,354 @@
+#![allow(non_snake_case)]
+
+use crate::json_structure::{Data, VaultSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// which pairs of snapshots are compared, ranges include min and exclude max
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Window {
+    // min <= secondBlock - firstBlock < max
+    BlockRange { min: u64, max: u64 },
+    // secondBlock - firstBlock == gap
+    FixedBlockGap { gap: u64 },
+    // min <= secondTimestamp - firstTimestamp < max, in seconds
+    TimestampRange { min: u64, max: u64 },
+    // |secondTimestamp - firstTimestamp - gap| <= tolerance, in seconds
+    FixedTimestampGap { gap: u64, tolerance: u64 },
+}
+
+impl Window {
+    // the window used before it was configurable: 0 < secondBlock - firstBlock < maxBlockGap
+    pub fn max_block_gap(maxBlockGap: u64) -> Window {
+        Window::BlockRange {
+            min: 1,
+            max: maxBlockGap,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Window::BlockRange { min, max } | Window::TimestampRange { min, max } => {
+                if *min == 0 {
+                    return Err("window: min gap must be positive".to_string());
+                }
+                if min >= max {
+                    return Err("window: min gap must be less than max gap".to_string());
+                }
+            }
+            Window::FixedBlockGap { gap } => {
+                if *gap == 0 {
+                    return Err("window: gap must be positive".to_string());
+                }
+            }
+            Window::FixedTimestampGap { gap, tolerance } => {
+                if gap <= tolerance {
+                    return Err("window: duration must be greater than the tolerance".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// "90s", "15m", "6h", "1.5d", "1w" or a plain number of seconds
+pub fn parse_duration(val: &str) -> Result<u64, String> {
+    let val = val.trim();
+    let (number, unit) = match val.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => val.split_at(index),
+        None => (val, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 604800.0,
+        _ => {
+            return Err(format!(
+                "unknown duration unit in {:?}, expected s, m, h, d or w",
+                val
+            ))
+        }
+    };
+    match number.trim().parse::<f64>() {
+        Ok(number) if number.is_finite() && number >= 0.0 => Ok((number * seconds).round() as u64),
+        _ => Err(format!("failed to parse duration from {:?}", val)),
+    }
+}
+
+// one vaultSet/<block> directory
+#[derive(Debug)]
+pub struct Snapshot<'a> {
+    pub block: u64,
+    pub blockKey: &'a String,
+    // None when the snapshot has no ilk
+    pub timestamp: Option<u64>,
+    pub vaultSets: &'a HashMap<String, VaultSet>,
+}
+
+// snapshots sorted by block number, directories that are not block numbers are left out
+#[derive(Debug)]
+pub struct BlockIndex<'a> {
+    snapshots: Vec<Snapshot<'a>>,
+}
+
+impl<'a> BlockIndex<'a> {
+    pub fn new(allVaultsAtBlock: &'a HashMap<String, HashMap<String, VaultSet>>) -> BlockIndex<'a> {
+        let mut snapshots: Vec<Snapshot> = allVaultsAtBlock
+            .iter()
+            .filter_map(|(blockKey, vaultSets)| {
+                let block = blockKey.parse::<u64>().ok()?;
+                // all ilks of a snapshot are read at the same block
+                let timestamp = vaultSets
+                    .values()
+                    .map(|vaultSet| vaultSet.timestamp.0)
+                    .min();
+                Some(Snapshot {
+                    block,
+                    blockKey,
+                    timestamp,
+                    vaultSets,
+                })
+            })
+            .collect();
+        snapshots.sort_by_key(|snapshot| snapshot.block);
+        BlockIndex { snapshots }
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot<'a>] {
+        &self.snapshots
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    // pairs in the window, ordered by first block then second block
+    pub fn pairs(&self, window: &Window) -> Vec<Data<'a>> {
+        match *window {
+            Window::BlockRange { min, max } => {
+                self.pairs_by(|snapshot| Some(snapshot.block), min, max)
+            }
+            Window::FixedBlockGap { gap } => {
+                self.pairs_by(|snapshot| Some(snapshot.block), gap, gap.saturating_add(1))
+            }
+            Window::TimestampRange { min, max } => {
+                self.pairs_by(|snapshot| snapshot.timestamp, min, max)
+            }
+            Window::FixedTimestampGap { gap, tolerance } => self.pairs_by(
+                |snapshot| snapshot.timestamp,
+                // pairs of snapshots at the same time are never compared
+                gap.saturating_sub(tolerance).max(1),
+                gap.saturating_add(tolerance).saturating_add(1),
+            ),
+        }
+    }
+
+    // two pointers over the snapshots sorted by key, for each first snapshot
+    // [lower, upper) are the second snapshots with min <= key gap < max
+    fn pairs_by<F>(&self, key: F, min: u64, max: u64) -> Vec<Data<'a>>
+    where
+        F: Fn(&Snapshot) -> Option<u64>,
+    {
+        let mut keyed: Vec<(u64, &Snapshot<'a>)> = self
+            .snapshots
+            .iter()
+            .filter_map(|snapshot| key(snapshot).map(|key| (key, snapshot)))
+            .collect();
+        // stable, so snapshots with the same key stay in block order
+        keyed.sort_by_key(|(key, _)| *key);
+
+        let mut dataset: Vec<Data> = vec![];
+        let mut lower = 0;
+        let mut upper = 0;
+        for (index, (firstKey, first)) in keyed.iter().enumerate() {
+            lower = lower.max(index + 1);
+            while lower < keyed.len() && keyed[lower].0 - firstKey < min {
+                lower += 1;
+            }
+            upper = upper.max(lower);
+            while upper < keyed.len() && keyed[upper].0 - firstKey < max {
+                upper += 1;
+            }
+            for (_, second) in &keyed[lower..upper] {
+                dataset.push(Data {
+                    firstBlock: first.blockKey.to_string(),
+                    secondBlock: second.blockKey.to_string(),
+                    vaultsAtFirstBlock: first.vaultSets,
+                    vaultsAtSecondBlock: second.vaultSets,
+                });
+            }
+        }
+        dataset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault_set(timestamp: u64) -> VaultSet {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": timestamp.to_string(),
+            "resultArray": [],
+            "price": "1",
+            "rate": "1",
+            "liquidationRatio": "1.5",
+        }))
+        .unwrap()
+    }
+
+    // irregular gaps, two snapshots at the same time, a snapshot without ilks and a
+    // directory that is not a block number
+    fn snapshots() -> HashMap<String, HashMap<String, VaultSet>> {
+        let mut allVaultsAtBlock: HashMap<String, HashMap<String, VaultSet>> = [
+            (100, 1000),
+            (103, 1036),
+            (104, 1048),
+            (110, 1120),
+            (111, 1120),
+            (130, 1360),
+            (150, 1600),
+            (151, 1612),
+        ]
+        .into_iter()
+        .map(|(block, timestamp)| {
+            (
+                block.to_string(),
+                HashMap::from([("ETH-A".to_string(), vault_set(timestamp))]),
+            )
+        })
+        .collect();
+        allVaultsAtBlock.insert("140".to_string(), HashMap::new());
+        allVaultsAtBlock.insert("notablock".to_string(), HashMap::new());
+        allVaultsAtBlock
+    }
+
+    fn in_window(window: &Window, first: &Snapshot, second: &Snapshot) -> bool {
+        let blockGap = second.block as i128 - first.block as i128;
+        let timestampGap = match (first.timestamp, second.timestamp) {
+            (Some(first), Some(second)) => Some(second as i128 - first as i128),
+            _ => None,
+        };
+        match *window {
+            Window::BlockRange { min, max } => min as i128 <= blockGap && blockGap < max as i128,
+            Window::FixedBlockGap { gap } => blockGap == gap as i128,
+            Window::TimestampRange { min, max } => {
+                timestampGap.is_some_and(|gap| min as i128 <= gap && gap < max as i128)
+            }
+            Window::FixedTimestampGap { gap, tolerance } => {
+                timestampGap.is_some_and(|timestampGap| {
+                    timestampGap > 0 && (timestampGap - gap as i128).abs() <= tolerance as i128
+                })
+            }
+        }
+    }
+
+    fn brute_force(index: &BlockIndex, window: &Window) -> Vec<(String, String)> {
+        let mut pairs: Vec<(String, String)> = vec![];
+        for first in index.snapshots() {
+            for second in index.snapshots() {
+                if in_window(window, first, second) {
+                    pairs.push((first.blockKey.clone(), second.blockKey.clone()));
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+
+    fn pairs(index: &BlockIndex, window: &Window) -> Vec<(String, String)> {
+        let mut pairs: Vec<(String, String)> = index
+            .pairs(window)
+            .into_iter()
+            .map(|data| (data.firstBlock, data.secondBlock))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn new_sorts_snapshots_and_skips_other_directories() {
+        let allVaultsAtBlock = snapshots();
+        let index = BlockIndex::new(&allVaultsAtBlock);
+        let blocks: Vec<u64> = index.snapshots().iter().map(|s| s.block).collect();
+        assert_eq!(blocks, [100, 103, 104, 110, 111, 130, 140, 150, 151]);
+        assert_eq!(index.snapshots()[6].timestamp, None);
+    }
+
+    #[test]
+    fn pairs_match_a_brute_force_filter() {
+        let allVaultsAtBlock = snapshots();
+        let index = BlockIndex::new(&allVaultsAtBlock);
+        let windows = [
+            Window::BlockRange { min: 1, max: 11 },
+            Window::BlockRange { min: 4, max: 31 },
+            Window::BlockRange { min: 1, max: 1000 },
+            Window::FixedBlockGap { gap: 1 },
+            Window::FixedBlockGap { gap: 10 },
+            Window::FixedBlockGap { gap: 1000 },
+            Window::TimestampRange { min: 1, max: 121 },
+            Window::TimestampRange { min: 12, max: 241 },
+            Window::TimestampRange {
+                min: 1,
+                max: 100000,
+            },
+            Window::FixedTimestampGap {
+                gap: 120,
+                tolerance: 0,
+            },
+            Window::FixedTimestampGap {
+                gap: 120,
+                tolerance: 12,
+            },
+            Window::FixedTimestampGap {
+                gap: 240,
+                tolerance: 239,
+            },
+        ];
+        for window in &windows {
+            assert_eq!(
+                pairs(&index, window),
+                brute_force(&index, window),
+                "{:?}",
+                window
+            );
+        }
+        assert!(!pairs(&index, &windows[10]).is_empty());
+    }
+
+    #[test]
+    fn pairs_are_ordered_by_first_then_second_block() {
+        let allVaultsAtBlock = snapshots();
+        let index = BlockIndex::new(&allVaultsAtBlock);
+        let blocks: Vec<(String, String)> = index
+            .pairs(&Window::BlockRange { min: 1, max: 11 })
+            .into_iter()
+            .map(|data| (data.firstBlock, data.secondBlock))
+            .collect();
+        let mut sorted = blocks.clone();
+        sorted.sort_by_key(|(first, second)| {
+            (
+                first.parse::<u64>().unwrap(),
+                second.parse::<u64>().unwrap(),
+            )
+        });
+        assert_eq!(blocks, sorted);
+    }
+
+    #[test]
+    fn parse_duration_reads_units() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("15m"), Ok(900));
+        assert_eq!(parse_duration("1.5d"), Ok(129600));
+        assert_eq!(parse_duration(" 1w "), Ok(604800));
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("-1h").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+#![allow(non_snake_case)]
+
+use crate::statistics;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// one day, in seconds
+pub const DEFAULT_BLOCK_LENGTH: u64 = 86400;
+pub const DEFAULT_CONFIDENCE: f64 = 0.95;
+
+// block bootstrap settings, data points are resampled by time blocks of their first-block
+// timestamp so correlated overlapping pairs stay together
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BootstrapOptions {
+    pub iterations: usize,
+    pub seed: u64,
+    // length of a time block in seconds
+    pub blockLength: u64,
+    // e.g. 0.95 for 95% intervals
+    pub confidence: f64,
+}
+
+impl BootstrapOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.iterations == 0 {
+            return Err("bootstrap: iterations must be positive".to_string());
+        }
+        if self.blockLength == 0 {
+            return Err("bootstrap: block length must be positive".to_string());
+        }
+        if !(self.confidence > 0.0 && self.confidence < 1.0) {
+            return Err("bootstrap: confidence must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+// one valid data point of a report
+#[derive(Clone, Copy, Debug)]
+pub struct BootstrapPoint {
+    pub firstTimestamp: u64,
+    pub dRatio: f64,
+    // capitalAtRiskValueLiq > capitalAtRiskValueRisk
+    pub plus: bool,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+// percentile intervals of the metrics over the resamples
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BootstrapReport {
+    pub iterations: usize,
+    pub blockCount: usize,
+    pub confidence: f64,
+    pub dRatioMean: Interval,
+    pub dRatioMedian: Interval,
+    pub plusCount: Interval,
+    pub minusCount: Interval,
+}
+
+// splitmix64, small and stable so a seed gives the same intervals in every version
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in 0..n, n > 0
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    // uniform in [0, 1)
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// None without data points, every report is resampled with the same seed so parameter sets
+// are compared on the same resamples
+pub fn bootstrap(points: &[BootstrapPoint], options: &BootstrapOptions) -> Option<BootstrapReport> {
+    let mut blocks: BTreeMap<u64, Vec<&BootstrapPoint>> = BTreeMap::new();
+    for point in points {
+        blocks
+            .entry(point.firstTimestamp / options.blockLength)
+            .or_default()
+            .push(point);
+    }
+    let blocks: Vec<Vec<&BootstrapPoint>> = blocks.into_values().collect();
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let mut rng = Rng(options.seed);
+    let mut means: Vec<f64> = vec![];
+    let mut medians: Vec<f64> = vec![];
+    let mut plusCounts: Vec<f64> = vec![];
+    let mut minusCounts: Vec<f64> = vec![];
+    let mut dRatios: Vec<f64> = vec![];
+    for _ in 0..options.iterations {
+        dRatios.clear();
+        let mut plusCount = 0;
+        for _ in 0..blocks.len() {
+            for point in &blocks[rng.below(blocks.len())] {
+                dRatios.push(point.dRatio.abs());
+                if point.plus {
+                    plusCount += 1;
+                }
+            }
+        }
+        // same definitions as dRatioMean and dRatioMedian of the report
+        means.push(dRatios.iter().sum::<f64>() / dRatios.len() as f64 * 100.0);
+        medians.push(statistics::upper_median(&dRatios).unwrap_or(f64::NAN));
+        plusCounts.push(plusCount as f64);
+        minusCounts.push((dRatios.len() - plusCount) as f64);
+    }
+
+    let interval = |values: &[f64]| Interval {
+        lower: statistics::percentile(values, (1.0 - options.confidence) / 2.0 * 100.0)
+            .unwrap_or(f64::NAN),
+        upper: statistics::percentile(values, (1.0 + options.confidence) / 2.0 * 100.0)
+            .unwrap_or(f64::NAN),
+    };
+    Some(BootstrapReport {
+        iterations: options.iterations,
+        blockCount: blocks.len(),
+        confidence: options.confidence,
+        dRatioMean: interval(&means),
+        dRatioMedian: interval(&medians),
+        plusCount: interval(&plusCounts),
+        minusCount: interval(&minusCounts),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(firstTimestamp: u64, dRatio: f64) -> BootstrapPoint {
+        BootstrapPoint {
+            firstTimestamp,
+            dRatio,
+            plus: dRatio > 0.0,
+        }
+    }
+
+    fn options(seed: u64) -> BootstrapOptions {
+        BootstrapOptions {
+            iterations: 1000,
+            seed,
+            blockLength: DEFAULT_BLOCK_LENGTH,
+            confidence: DEFAULT_CONFIDENCE,
+        }
+    }
+
+    fn intervals(report: &BootstrapReport) -> [Interval; 4] {
+        [
+            report.dRatioMean,
+            report.dRatioMedian,
+            report.plusCount,
+            report.minusCount,
+        ]
+    }
+
+    fn points() -> Vec<BootstrapPoint> {
+        (0..40)
+            .map(|i| point(i * 20000, ((i * 7919) % 13) as f64 / 10.0 - 0.6))
+            .collect()
+    }
+
+    #[test]
+    fn no_points_give_no_report() {
+        assert!(bootstrap(&[], &options(0)).is_none());
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_intervals() {
+        let points = points();
+        let first = bootstrap(&points, &options(7)).unwrap();
+        let second = bootstrap(&points, &options(7)).unwrap();
+        for (a, b) in intervals(&first).iter().zip(&intervals(&second)) {
+            assert_eq!(a.lower.to_bits(), b.lower.to_bits());
+            assert_eq!(a.upper.to_bits(), b.upper.to_bits());
+        }
+        for seed in 0..5 {
+            let report = bootstrap(&points, &options(seed)).unwrap();
+            for interval in intervals(&report) {
+                assert!(interval.lower <= interval.upper, "{:?}", interval);
+            }
+        }
+    }
+
+    #[test]
+    fn points_are_grouped_by_time_block() {
+        let points = [
+            point(0, 0.5),
+            point(86399, 0.25),
+            point(86400, 0.1),
+            point(86400 * 3 + 5, -0.2),
+        ];
+        let report = bootstrap(&points, &options(0)).unwrap();
+        assert_eq!(report.blockCount, 3);
+        let report = bootstrap(
+            &points,
+            &BootstrapOptions {
+                blockLength: 3600,
+                ..options(0)
+            },
+        )
+        .unwrap();
+        assert_eq!(report.blockCount, 4);
+    }
+
+    #[test]
+    fn points_of_a_block_are_resampled_together() {
+        // one block of three plus points and one of a minus point, resamples draw two
+        // blocks, so six plus points only happen when the three move together
+        let points = [
+            point(10, 0.1),
+            point(20, 0.2),
+            point(30, 0.3),
+            point(86400, -0.4),
+        ];
+        let report = bootstrap(&points, &options(1)).unwrap();
+        assert_eq!(report.blockCount, 2);
+        assert_eq!((report.plusCount.lower, report.plusCount.upper), (0.0, 6.0));
+        assert_eq!(
+            (report.minusCount.lower, report.minusCount.upper),
+            (0.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn a_single_block_gives_the_sample_statistics() {
+        let points = [point(10, 0.1), point(20, -0.4), point(30, 0.3)];
+        let report = bootstrap(&points, &options(3)).unwrap();
+        assert_eq!(report.blockCount, 1);
+        let mean = (0.1 + 0.4 + 0.3) / 3.0 * 100.0;
+        assert!((report.dRatioMean.lower - mean).abs() < 1e-9);
+        assert!((report.dRatioMean.upper - mean).abs() < 1e-9);
+        assert_eq!(report.dRatioMedian.lower, 0.3);
+        assert_eq!(report.dRatioMedian.upper, 0.3);
+        assert_eq!((report.plusCount.lower, report.plusCount.upper), (2.0, 2.0));
+    }
+}
@@ -0,0 +1,636 @@
+#![allow(non_snake_case)]
+
+use crate::backtest::{ActualLiquidation, Arithmetic, IlkSelection, MissingVaultPolicy};
+use crate::block_index::{parse_duration, Window};
+use crate::bootstrap::{BootstrapOptions, DEFAULT_BLOCK_LENGTH, DEFAULT_CONFIDENCE};
+use crate::error::Error;
+use crate::optimizer::{Objective, DEFAULT_UNDERESTIMATION_WEIGHT};
+use crate::strategy::{
+    LinearRampStrategy, LiquidationStrategy, LogisticStrategy, StepTableStrategy, ThresholdStrategy,
+};
+use crate::validation::{Folds, ValidationOptions};
+use crate::vault_filter::VaultFilter;
+use serde::de::{Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Run configuration describing one parameter sweep, loaded from a .toml or .json file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    pub data: DataSources,
+    #[serde(default)]
+    pub ilks: IlkConfig,
+    #[serde(default)]
+    pub window: WindowConfig,
+    pub strategies: Vec<StrategyConfig>,
+    // "float" or "fixed-point"
+    #[serde(default)]
+    pub arithmetic: Arithmetic,
+    // "skip", "count" or "fail" for vaults closed at the second block or missing from history
+    #[serde(default)]
+    pub missingVaults: MissingVaultPolicy,
+    // "full-debt", "liquidated-debt" or "seized-collateral-value"
+    #[serde(default)]
+    pub actualLiquidation: ActualLiquidation,
+    // vaults taking part in the transitions, every vault with collateral and debt by default
+    #[serde(default)]
+    pub vaultFilter: VaultFilter,
+    // block bootstrap confidence intervals, off when missing
+    #[serde(default)]
+    pub bootstrap: Option<BootstrapConfig>,
+    // train/test or walk-forward validation of the best strategy, off when missing
+    #[serde(default)]
+    pub validation: Option<ValidationConfig>,
+    // worker threads, defaults to the number of cores
+    #[serde(default)]
+    pub threads: Option<usize>,
+    // directory for dratio.json and dratio.csv
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+    // JSON Lines file with every evaluated block pair
+    #[serde(default)]
+    pub trace: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSources {
+    pub vaultHistory: PathBuf,
+    pub vaultSet: String,
+    // file with the precomputed transitions, reused when it was built with the same settings
+    #[serde(default)]
+    pub transitions: Option<PathBuf>,
+}
+
+// either "all" or a list of ilk names
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IlkConfig {
+    All(AllIlks),
+    Only(Vec<String>),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AllIlks {
+    All,
+}
+
+impl Default for IlkConfig {
+    fn default() -> IlkConfig {
+        IlkConfig::Only(vec!["ETH-A".to_string()])
+    }
+}
+
+impl IlkConfig {
+    pub fn selection(&self) -> IlkSelection {
+        match self {
+            IlkConfig::All(_) => IlkSelection::All,
+            IlkConfig::Only(ilks) => IlkSelection::Only(ilks.clone()),
+        }
+    }
+}
+
+// one of
+// - `maxBlockGap` with an optional `minBlockGap` (minBlockGap <= gap < maxBlockGap)
+// - a fixed `blockGap`
+// - `maxDuration` with an optional `minDuration`, by snapshot timestamp
+// - a fixed `duration` with an optional `tolerance`
+// a single struct rather than an untagged enum, so a wrong combination gets a message
+// naming the keys
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowConfig {
+    #[serde(default)]
+    pub minBlockGap: Option<u64>,
+    #[serde(default)]
+    pub maxBlockGap: Option<u64>,
+    #[serde(default)]
+    pub blockGap: Option<u64>,
+    #[serde(default)]
+    pub minDuration: Option<Duration>,
+    #[serde(default)]
+    pub maxDuration: Option<Duration>,
+    #[serde(default)]
+    pub duration: Option<Duration>,
+    #[serde(default)]
+    pub tolerance: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BootstrapConfig {
+    pub iterations: usize,
+    #[serde(default)]
+    pub seed: u64,
+    // time blocks the data points are resampled by, one day by default
+    #[serde(default = "default_block_length")]
+    pub blockLength: Duration,
+    #[serde(default = "default_confidence")]
+    pub confidence: f64,
+}
+
+// one of splitBlock and walkForwardBlocks
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidationConfig {
+    // train on the pairs before this block, test on the pairs from it on
+    #[serde(default)]
+    pub splitBlock: Option<u64>,
+    // train on window k and test on window k + 1 of this many blocks
+    #[serde(default)]
+    pub walkForwardBlocks: Option<u64>,
+    // "mean", "median", "rmse" or "asymmetric", minimized on the train pairs
+    #[serde(default)]
+    pub objective: Objective,
+    #[serde(default = "default_underestimation_weight")]
+    pub underestimationWeight: f64,
+}
+
+fn default_underestimation_weight() -> f64 {
+    DEFAULT_UNDERESTIMATION_WEIGHT
+}
+
+impl ValidationConfig {
+    pub fn folds(&self) -> Option<Folds> {
+        match (self.splitBlock, self.walkForwardBlocks) {
+            (Some(splitBlock), None) => Some(Folds::TrainTest { splitBlock }),
+            (None, Some(windowBlocks)) => Some(Folds::WalkForward { windowBlocks }),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let Some(folds) = self.folds() else {
+            return Err(
+                "validation: exactly one of splitBlock and walkForwardBlocks is required"
+                    .to_string(),
+            );
+        };
+        ValidationOptions {
+            folds,
+            objective: self.objective,
+            underestimationWeight: self.underestimationWeight,
+        }
+        .validate()
+    }
+}
+
+fn default_block_length() -> Duration {
+    Duration(DEFAULT_BLOCK_LENGTH)
+}
+
+fn default_confidence() -> f64 {
+    DEFAULT_CONFIDENCE
+}
+
+impl BootstrapConfig {
+    pub fn options(&self) -> BootstrapOptions {
+        BootstrapOptions {
+            iterations: self.iterations,
+            seed: self.seed,
+            blockLength: self.blockLength.0,
+            confidence: self.confidence,
+        }
+    }
+}
+
+fn default_min_block_gap() -> u64 {
+    1
+}
+
+fn default_min_duration() -> Duration {
+    Duration(1)
+}
+
+// seconds, written as "6h", "7d" or a plain number of seconds
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct Duration(pub u64);
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MyVisitor;
+
+        impl<'de> Visitor<'de> for MyVisitor {
+            type Value = Duration;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt.write_str("duration such as \"6h\" or a number of seconds")
+            }
+
+            fn visit_u64<E>(self, val: u64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(Duration(val))
+            }
+
+            fn visit_i64<E>(self, val: i64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                u64::try_from(val)
+                    .map(Duration)
+                    .map_err(|_| E::custom(format!("negative duration {}", val)))
+            }
+
+            fn visit_str<E>(self, val: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                parse_duration(val).map(Duration).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(MyVisitor)
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> WindowConfig {
+        // 40000 blocks = around one week
+        WindowConfig {
+            minBlockGap: Some(1),
+            maxBlockGap: Some(10000),
+            blockGap: None,
+            minDuration: None,
+            maxDuration: None,
+            duration: None,
+            tolerance: None,
+        }
+    }
+}
+
+impl WindowConfig {
+    pub fn window(&self) -> Result<Window, String> {
+        // (key, kind of window, present)
+        let keys = [
+            ("minBlockGap", 0, self.minBlockGap.is_some()),
+            ("maxBlockGap", 0, self.maxBlockGap.is_some()),
+            ("blockGap", 1, self.blockGap.is_some()),
+            ("minDuration", 2, self.minDuration.is_some()),
+            ("maxDuration", 2, self.maxDuration.is_some()),
+            ("duration", 3, self.duration.is_some()),
+            ("tolerance", 3, self.tolerance.is_some()),
+        ];
+        let mut present = keys.iter().filter(|(_, _, present)| *present);
+        if let Some((key, kind, _)) = present.next() {
+            if let Some((other, _, _)) = present.find(|(_, otherKind, _)| otherKind != kind) {
+                return Err(format!("window: {} can't be combined with {}", key, other));
+            }
+        }
+        match *self {
+            WindowConfig {
+                maxBlockGap: Some(maxBlockGap),
+                ..
+            } => Ok(Window::BlockRange {
+                min: self.minBlockGap.unwrap_or_else(default_min_block_gap),
+                max: maxBlockGap,
+            }),
+            WindowConfig {
+                blockGap: Some(blockGap),
+                ..
+            } => Ok(Window::FixedBlockGap { gap: blockGap }),
+            WindowConfig {
+                maxDuration: Some(maxDuration),
+                ..
+            } => Ok(Window::TimestampRange {
+                min: self.minDuration.unwrap_or_else(default_min_duration).0,
+                max: maxDuration.0,
+            }),
+            WindowConfig {
+                duration: Some(duration),
+                ..
+            } => Ok(Window::FixedTimestampGap {
+                gap: duration.0,
+                tolerance: self.tolerance.unwrap_or_default().0,
+            }),
+            WindowConfig {
+                minBlockGap: Some(_),
+                ..
+            } => Err("window: minBlockGap needs maxBlockGap".to_string()),
+            WindowConfig {
+                minDuration: Some(_),
+                ..
+            } => Err("window: minDuration needs maxDuration".to_string()),
+            WindowConfig {
+                tolerance: Some(_), ..
+            } => Err("window: tolerance needs duration".to_string()),
+            _ => Err(
+                "window: one of maxBlockGap, blockGap, maxDuration and duration is required"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+// explicit list `[0.5, 0.4]` or inclusive grid `{ start = 0.2, stop = 0.5, step = 0.1 }`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParameterRange {
+    List(Vec<f64>),
+    Grid { start: f64, stop: f64, step: f64 },
+}
+
+impl ParameterRange {
+    pub fn values(&self) -> Vec<f64> {
+        match self {
+            ParameterRange::List(values) => values.clone(),
+            ParameterRange::Grid { start, stop, step } => {
+                // tolerate float error on the last step so stop is included,
+                // and round so 0.2 + 0.1 prints as 0.3
+                let count = ((stop - start) / step + 1e-9).floor() as usize + 1;
+                (0..count)
+                    .map(|i| ((start + step * i as f64) * 1e12).round() / 1e12)
+                    .collect()
+            }
+        }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        match self {
+            ParameterRange::List(values) => {
+                if values.is_empty() {
+                    return Err(format!("{}: empty list", name));
+                }
+                if values.iter().any(|value| !value.is_finite()) {
+                    return Err(format!("{}: values must be finite", name));
+                }
+            }
+            ParameterRange::Grid { start, stop, step } => {
+                if !(start.is_finite() && stop.is_finite() && step.is_finite()) {
+                    return Err(format!("{}: start, stop and step must be finite", name));
+                }
+                if *step <= 0.0 {
+                    return Err(format!("{}: step must be positive", name));
+                }
+                if start > stop {
+                    return Err(format!("{}: start is greater than stop", name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum StrategyConfig {
+    Threshold {
+        threshold: ParameterRange,
+        coefficient: ParameterRange,
+    },
+    Linear {
+        lower: ParameterRange,
+        upper: ParameterRange,
+        coefficient: ParameterRange,
+    },
+    // each table is a list of (safetyLevel, coefficient)
+    Step {
+        tables: Vec<Vec<(f64, f64)>>,
+    },
+    Logistic {
+        midpoint: ParameterRange,
+        steepness: ParameterRange,
+        coefficient: ParameterRange,
+    },
+}
+
+impl StrategyConfig {
+    // expands the parameter ranges into one strategy per combination
+    pub fn strategies(&self) -> Vec<Box<dyn LiquidationStrategy>> {
+        let mut strategies: Vec<Box<dyn LiquidationStrategy>> = vec![];
+        match self {
+            StrategyConfig::Threshold {
+                threshold,
+                coefficient,
+            } => {
+                for coefficient in coefficient.values() {
+                    for threshold in threshold.values() {
+                        strategies.push(Box::new(ThresholdStrategy {
+                            threshold,
+                            coefficient,
+                        }));
+                    }
+                }
+            }
+            StrategyConfig::Linear {
+                lower,
+                upper,
+                coefficient,
+            } => {
+                for coefficient in coefficient.values() {
+                    for lower in lower.values() {
+                        for upper in upper.values() {
+                            if lower < upper {
+                                strategies.push(Box::new(LinearRampStrategy {
+                                    lower,
+                                    upper,
+                                    coefficient,
+                                }));
+                            }
+                        }
+                    }
+                }
+            }
+            StrategyConfig::Step { tables } => {
+                for table in tables {
+                    strategies.push(Box::new(StepTableStrategy::new(table.clone())));
+                }
+            }
+            StrategyConfig::Logistic {
+                midpoint,
+                steepness,
+                coefficient,
+            } => {
+                for coefficient in coefficient.values() {
+                    for midpoint in midpoint.values() {
+                        for steepness in steepness.values() {
+                            strategies.push(Box::new(LogisticStrategy {
+                                midpoint,
+                                steepness,
+                                coefficient,
+                            }));
+                        }
+                    }
+                }
+            }
+        }
+        strategies
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            StrategyConfig::Threshold {
+                threshold,
+                coefficient,
+            } => {
+                threshold.validate("threshold.threshold")?;
+                coefficient.validate("threshold.coefficient")?;
+            }
+            StrategyConfig::Linear {
+                lower,
+                upper,
+                coefficient,
+            } => {
+                lower.validate("linear.lower")?;
+                upper.validate("linear.upper")?;
+                coefficient.validate("linear.coefficient")?;
+                if self.strategies().is_empty() {
+                    return Err("linear: no combination with lower < upper".to_string());
+                }
+            }
+            StrategyConfig::Step { tables } => {
+                if tables.is_empty() || tables.iter().any(|table| table.is_empty()) {
+                    return Err("step: tables must not be empty".to_string());
+                }
+                if tables.iter().flatten().any(|(safetyLevel, coefficient)| {
+                    !safetyLevel.is_finite() || !coefficient.is_finite()
+                }) {
+                    return Err("step: values must be finite".to_string());
+                }
+            }
+            StrategyConfig::Logistic {
+                midpoint,
+                steepness,
+                coefficient,
+            } => {
+                midpoint.validate("logistic.midpoint")?;
+                steepness.validate("logistic.steepness")?;
+                coefficient.validate("logistic.coefficient")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RunConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let IlkConfig::Only(ilks) = &self.ilks {
+            if ilks.is_empty() {
+                return Err("ilks: empty list, use \"all\" to select every ilk".to_string());
+            }
+        }
+        self.window.window()?.validate()?;
+        if let Some(bootstrap) = &self.bootstrap {
+            bootstrap.options().validate()?;
+        }
+        if let Some(validation) = &self.validation {
+            validation.validate()?;
+        }
+        if self.strategies.is_empty() {
+            return Err("strategies: at least one strategy is required".to_string());
+        }
+        for strategy in &self.strategies {
+            strategy.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn strategies(&self) -> Vec<Box<dyn LiquidationStrategy>> {
+        self.strategies
+            .iter()
+            .flat_map(|strategy| strategy.strategies())
+            .collect()
+    }
+}
+
+// format is chosen by file extension, .toml or .json
+pub fn read_run_config<P: AsRef<Path>>(path: P) -> Result<RunConfig, Error> {
+    let path = path.as_ref();
+    let configError = |message: String| Error::Config {
+        path: path.to_path_buf(),
+        message,
+    };
+    let contents = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let config: RunConfig = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str(&contents).map_err(|e| configError(e.to_string()))?,
+        Some("json") => serde_json::from_str(&contents).map_err(|e| Error::json(path, &e))?,
+        _ => {
+            return Err(configError(
+                "unknown run config format, expected .toml or .json".to_string(),
+            ))
+        }
+    };
+    config.validate().map_err(configError)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(toml: &str) -> Result<Window, String> {
+        toml::from_str::<WindowConfig>(toml)
+            .map_err(|e| e.to_string())?
+            .window()
+    }
+
+    #[test]
+    fn window_keys_map_to_windows_with_defaults() {
+        assert_eq!(
+            window("maxBlockGap = 100").unwrap(),
+            Window::BlockRange { min: 1, max: 100 }
+        );
+        assert_eq!(
+            window("blockGap = 50").unwrap(),
+            Window::FixedBlockGap { gap: 50 }
+        );
+        assert_eq!(
+            window("minDuration = \"1h\"\nmaxDuration = \"1d\"").unwrap(),
+            Window::TimestampRange {
+                min: 3600,
+                max: 86400
+            }
+        );
+        assert_eq!(
+            window("duration = \"6h\"").unwrap(),
+            Window::FixedTimestampGap {
+                gap: 21600,
+                tolerance: 0
+            }
+        );
+        assert_eq!(
+            WindowConfig::default().window().unwrap(),
+            Window::BlockRange { min: 1, max: 10000 }
+        );
+    }
+
+    #[test]
+    fn window_key_combinations_are_named_in_the_error() {
+        assert_eq!(
+            window("maxBlockGap = 100\nduration = \"6h\"").unwrap_err(),
+            "window: maxBlockGap can't be combined with duration"
+        );
+        assert_eq!(
+            window("blockGap = 50\nminBlockGap = 2").unwrap_err(),
+            "window: minBlockGap can't be combined with blockGap"
+        );
+        assert_eq!(
+            window("minBlockGap = 2").unwrap_err(),
+            "window: minBlockGap needs maxBlockGap"
+        );
+        assert_eq!(
+            window("minDuration = \"1h\"").unwrap_err(),
+            "window: minDuration needs maxDuration"
+        );
+        assert_eq!(
+            window("tolerance = \"15m\"").unwrap_err(),
+            "window: tolerance needs duration"
+        );
+        assert_eq!(
+            window("").unwrap_err(),
+            "window: one of maxBlockGap, blockGap, maxDuration and duration is required"
+        );
+        assert!(window("maxBlockGapp = 100")
+            .unwrap_err()
+            .contains("unknown field `maxBlockGapp`"));
+    }
+}
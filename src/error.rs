@@ -0,0 +1,153 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // malformed json, or json that does not match the expected structure
+    Json {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // a numeric field of a vault set that could not be parsed
+    InvalidNumber(Box<InvalidNumber>),
+    Config {
+        path: PathBuf,
+        message: String,
+    },
+    // block is None when the ilk is not present in any snapshot
+    MissingIlk {
+        ilk: String,
+        block: Option<String>,
+    },
+    // vault present at the first block of a pair but not at the second
+    MissingVault {
+        vault: String,
+        block: String,
+    },
+    // vault present in a snapshot but not in vaultHistory.json
+    MissingLiquidationHistory {
+        vault: String,
+    },
+    ThreadPool {
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct InvalidNumber {
+    pub path: PathBuf,
+    pub block: String,
+    pub ilk: Option<String>,
+    pub vault: Option<String>,
+    // e.g. ETH-A.resultArray[3].debt
+    pub field: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Error {
+    pub fn io<P: AsRef<Path>>(path: P, source: io::Error) -> Error {
+        Error::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn json<P: AsRef<Path>>(path: P, e: &serde_json::Error) -> Error {
+        if e.is_io() {
+            return Error::io(path, io::Error::other(e.to_string()));
+        }
+        Error::Json {
+            path: path.as_ref().to_path_buf(),
+            line: e.line(),
+            column: e.column(),
+            message: message_without_position(e),
+        }
+    }
+}
+
+// serde_json appends " at line X column Y" to its messages
+pub(crate) fn message_without_position(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+    let suffix = format!(" at line {} column {}", e.line(), e.column());
+    match message.strip_suffix(&suffix) {
+        Some(message) => message.to_string(),
+        None => message,
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Json {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "{}:{}:{}: invalid json: {}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            Error::InvalidNumber(e) => {
+                write!(
+                    f,
+                    "{}:{}:{}: block {}",
+                    e.path.display(),
+                    e.line,
+                    e.column,
+                    e.block
+                )?;
+                if let Some(ilk) = &e.ilk {
+                    write!(f, ", ilk {}", ilk)?;
+                }
+                if let Some(vault) = &e.vault {
+                    write!(f, ", vault {}", vault)?;
+                }
+                write!(f, ": invalid number at {}: {}", e.field, e.message)
+            }
+            Error::Config { path, message } => write!(f, "{}: {}", path.display(), message),
+            Error::MissingIlk { ilk, block: None } => {
+                write!(f, "ilk {} is not present in any snapshot", ilk)
+            }
+            Error::MissingIlk {
+                ilk,
+                block: Some(block),
+            } => write!(f, "ilk {} is not present at block {}", ilk, block),
+            Error::MissingVault { vault, block } => {
+                write!(
+                    f,
+                    "vault {} is missing from the snapshot at block {}",
+                    vault, block
+                )
+            }
+            Error::MissingLiquidationHistory { vault } => {
+                write!(f, "vault {} is missing from the vault history", vault)
+            }
+            Error::ThreadPool { message } => {
+                write!(f, "failed to start worker threads: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -1,14 +1,138 @@
 #![allow(non_snake_case)]
 
+use ethnum::I256;
 use serde::de::{Deserializer, Visitor};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct VaultLog {
-    pub __typename: String,
-    pub timestamp: String,
+// fields every subgraph log has, block and transaction are missing from older exports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogInfo {
+    pub timestamp: StringOrU64,
+    #[serde(default)]
+    pub block: Option<StringOrU64>,
+    #[serde(default)]
+    pub transaction: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CreationLog {
+    #[serde(flatten)]
+    pub info: LogInfo,
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CollateralChangeLog {
+    #[serde(flatten)]
+    pub info: LogInfo,
+    #[serde(default)]
+    pub collateralBefore: Option<StringOrWad>,
+    #[serde(default)]
+    pub collateralAfter: Option<StringOrWad>,
+    #[serde(default)]
+    pub collateralDiff: Option<StringOrWad>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DebtChangeLog {
+    #[serde(flatten)]
+    pub info: LogInfo,
+    #[serde(default)]
+    pub debtBefore: Option<StringOrWad>,
+    #[serde(default)]
+    pub debtAfter: Option<StringOrWad>,
+    #[serde(default)]
+    pub debtDiff: Option<StringOrWad>,
+}
+
+// collateral and debt are what the liquidation took from the vault
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LiquidationStartLog {
+    #[serde(flatten)]
+    pub info: LogInfo,
+    #[serde(default)]
+    pub auctionId: Option<String>,
+    #[serde(default)]
+    pub collateral: Option<StringOrWad>,
+    #[serde(default)]
+    pub debt: Option<StringOrWad>,
+    #[serde(default)]
+    pub initialPrice: Option<StringOrRay>,
+}
+
+// remainingCollateral is returned to the vault when the auction ends
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LiquidationFinishLog {
+    #[serde(flatten)]
+    pub info: LogInfo,
+    #[serde(default)]
+    pub auctionId: Option<String>,
+    #[serde(default)]
+    pub remainingCollateral: Option<StringOrWad>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransferLog {
+    #[serde(flatten)]
+    pub info: LogInfo,
+    #[serde(default)]
+    pub previousOwner: Option<String>,
+    #[serde(default)]
+    pub nextOwner: Option<String>,
+}
+
+// collateral and debt moved between two vaults of the same ilk
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SplitChangeLog {
+    #[serde(flatten)]
+    pub info: LogInfo,
+    #[serde(default)]
+    pub src: Option<String>,
+    #[serde(default)]
+    pub dst: Option<String>,
+    #[serde(default)]
+    pub collateralToMove: Option<StringOrWad>,
+    #[serde(default)]
+    pub debtToMove: Option<StringOrWad>,
+}
+
+// one entry of a vault's logs, tagged by the subgraph's __typename
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "__typename", rename_all = "camelCase")]
+pub enum VaultLog {
+    CreationLog(CreationLog),
+    CollateralChangeLog(CollateralChangeLog),
+    DebtChangeLog(DebtChangeLog),
+    LiquidationStartLog(LiquidationStartLog),
+    LiquidationFinishLog(LiquidationFinishLog),
+    TransferLog(TransferLog),
+    SplitChangeLog(SplitChangeLog),
+    // log types this tool does not use
+    #[serde(other)]
+    Other,
+}
+
+impl VaultLog {
+    // None for logs of an unknown type
+    pub fn info(&self) -> Option<&LogInfo> {
+        match self {
+            VaultLog::CreationLog(log) => Some(&log.info),
+            VaultLog::CollateralChangeLog(log) => Some(&log.info),
+            VaultLog::DebtChangeLog(log) => Some(&log.info),
+            VaultLog::LiquidationStartLog(log) => Some(&log.info),
+            VaultLog::LiquidationFinishLog(log) => Some(&log.info),
+            VaultLog::TransferLog(log) => Some(&log.info),
+            VaultLog::SplitChangeLog(log) => Some(&log.info),
+            VaultLog::Other => None,
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<u64> {
+        self.info().map(|info| info.timestamp.0)
+    }
 }
 
 #[allow(non_snake_case)]
@@ -45,7 +169,7 @@ impl<'de> Deserialize<'de> for StringOrF64 {
             where
                 E: serde::de::Error,
             {
-                Ok(StringOrF64(val as f64))
+                Ok(StringOrF64(val))
             }
 
             fn visit_u64<E>(self, val: u64) -> Result<Self::Value, E>
@@ -55,13 +179,20 @@ impl<'de> Deserialize<'de> for StringOrF64 {
                 Ok(StringOrF64(val as f64))
             }
 
+            fn visit_i64<E>(self, val: i64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(StringOrF64(val as f64))
+            }
+
             fn visit_str<E>(self, val: &str) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
                 match val.parse::<f64>() {
                     Ok(val) => self.visit_f64(val),
-                    Err(_) => Err(E::custom("failed to parse f64")),
+                    Err(_) => Err(E::custom(format!("failed to parse f64 from {:?}", val))),
                 }
             }
         }
@@ -70,25 +201,299 @@ impl<'de> Deserialize<'de> for StringOrF64 {
     }
 }
 
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct StringOrU64(pub u64);
+
+impl<'de> Deserialize<'de> for StringOrU64 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MyVisitor;
+
+        impl<'de> Visitor<'de> for MyVisitor {
+            type Value = StringOrU64;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt.write_str("u64 or string")
+            }
+
+            fn visit_u64<E>(self, val: u64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(StringOrU64(val))
+            }
+
+            fn visit_str<E>(self, val: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                match val.parse::<u64>() {
+                    Ok(val) => self.visit_u64(val),
+                    Err(_) => Err(E::custom(format!("failed to parse u64 from {:?}", val))),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(MyVisitor)
+    }
+}
+
+// fixed point number with DECIMALS decimals, as used by the Maker contracts
+// (wad = 18, ray = 27, rad = 45)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixedPoint<const DECIMALS: u32>(pub I256);
+
+pub type Wad = FixedPoint<18>;
+pub type Ray = FixedPoint<27>;
+pub type Rad = FixedPoint<45>;
+
+fn power_of_ten(exponent: u32) -> Option<I256> {
+    I256::new(10).checked_pow(exponent)
+}
+
+impl<const DECIMALS: u32> FixedPoint<DECIMALS> {
+    pub const ZERO: FixedPoint<DECIMALS> = FixedPoint(I256::ZERO);
+
+    // accepts "-12.345" and "1.2e-5", digits beyond DECIMALS are truncated
+    pub fn parse(val: &str) -> Option<FixedPoint<DECIMALS>> {
+        let (number, exponent) = match val.find(['e', 'E']) {
+            Some(index) => (&val[..index], val[index + 1..].parse::<i64>().ok()?),
+            None => (val, 0),
+        };
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        if !integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+
+        // value = digits * 10^shift / 10^DECIMALS
+        let mut digits = format!("{}{}", integer, fraction);
+        // exponents near i64::MAX would overflow, such values are out of range anyway
+        let mut shift = exponent
+            .checked_sub(fraction.len() as i64)?
+            .checked_add(DECIMALS as i64)?;
+        if shift < 0 {
+            let dropped = shift.saturating_neg().min(digits.len() as i64);
+            digits.truncate(digits.len() - dropped as usize);
+            shift += dropped;
+        }
+        let digits = digits.trim_start_matches('0');
+        if digits.is_empty() || shift < 0 {
+            return Some(FixedPoint::ZERO);
+        }
+        let mantissa = I256::from_str_radix(digits, 10)
+            .ok()?
+            .checked_mul(power_of_ten(u32::try_from(shift).ok()?)?)?;
+        Some(FixedPoint(if negative { -mantissa } else { mantissa }))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0.as_f64() / 10f64.powi(DECIMALS as i32)
+    }
+
+    pub fn checked_add(self, other: FixedPoint<DECIMALS>) -> Option<FixedPoint<DECIMALS>> {
+        self.0.checked_add(other.0).map(FixedPoint)
+    }
+
+    // product with RESULT decimals, rounded half away from zero like ds-math wmul/rmul
+    pub fn checked_mul<const OTHER: u32, const RESULT: u32>(
+        self,
+        other: FixedPoint<OTHER>,
+    ) -> Option<FixedPoint<RESULT>> {
+        let product = self.0.checked_mul(other.0)?;
+        let decimals = DECIMALS + OTHER;
+        if decimals < RESULT {
+            return product
+                .checked_mul(power_of_ten(RESULT - decimals)?)
+                .map(FixedPoint);
+        }
+        let divisor = power_of_ten(decimals - RESULT)?;
+        let half = divisor / 2;
+        let rounded = if product.is_negative() {
+            product.checked_sub(half)?
+        } else {
+            product.checked_add(half)?
+        };
+        Some(FixedPoint(rounded / divisor))
+    }
+
+    // quotient with RESULT decimals, rounded towards zero like ds-math rdiv and the
+    // Spotter's spot price
+    pub fn checked_div<const OTHER: u32, const RESULT: u32>(
+        self,
+        other: FixedPoint<OTHER>,
+    ) -> Option<FixedPoint<RESULT>> {
+        if other.0 == I256::ZERO {
+            return None;
+        }
+        // a / 10^DECIMALS / (b / 10^OTHER) = (a * 10^(RESULT + OTHER - DECIMALS) / b) / 10^RESULT
+        let scale = (RESULT + OTHER).checked_sub(DECIMALS);
+        let quotient = match scale {
+            Some(scale) => self.0.checked_mul(power_of_ten(scale)?)? / other.0,
+            None => self.0 / other.0 / power_of_ten(DECIMALS - RESULT - OTHER)?,
+        };
+        Some(FixedPoint(quotient))
+    }
+}
+
+impl<const DECIMALS: u32> fmt::Display for FixedPoint<DECIMALS> {
+    // shortest exact form, "1.05" rather than "1.050000000000000000"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!(
+            "{:0>width$}",
+            self.0.unsigned_abs().to_string(),
+            width = DECIMALS as usize + 1
+        );
+        let (integer, fraction) = digits.split_at(digits.len() - DECIMALS as usize);
+        let fraction = fraction.trim_end_matches('0');
+        if self.0.is_negative() {
+            f.write_str("-")?;
+        }
+        f.write_str(integer)?;
+        if !fraction.is_empty() {
+            write!(f, ".{}", fraction)?;
+        }
+        Ok(())
+    }
+}
+
+// parsed both as f64 and as exact fixed point, the fixed point value is None when the
+// input is not representable (NaN, infinite or out of range)
+#[derive(Clone, Copy, Debug)]
+pub struct StringOrDecimal<const DECIMALS: u32>(pub f64, pub Option<FixedPoint<DECIMALS>>);
+
+pub type StringOrWad = StringOrDecimal<18>;
+pub type StringOrRay = StringOrDecimal<27>;
+
+impl<const DECIMALS: u32> StringOrDecimal<DECIMALS> {
+    // None when any value is None, the exact sum is None when it overflows
+    pub fn sum<I>(values: I) -> Option<StringOrDecimal<DECIMALS>>
+    where
+        I: IntoIterator<Item = Option<StringOrDecimal<DECIMALS>>>,
+    {
+        let mut total = StringOrDecimal(0.0, Some(FixedPoint::ZERO));
+        for value in values {
+            let value = value?;
+            total = StringOrDecimal(
+                total.0 + value.0,
+                total.1.zip(value.1).and_then(|(a, b)| a.checked_add(b)),
+            );
+        }
+        Some(total)
+    }
+}
+
+impl<const DECIMALS: u32> fmt::Display for StringOrDecimal<DECIMALS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            Some(fixed) => fixed.fmt(f),
+            None => self.0.fmt(f),
+        }
+    }
+}
+
+// exact values are written as strings so they round trip without going through f64
+impl<const DECIMALS: u32> Serialize for StringOrDecimal<DECIMALS> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.1 {
+            Some(fixed) => serializer.collect_str(&fixed),
+            None => serializer.serialize_f64(self.0),
+        }
+    }
+}
+
+impl<'de, const DECIMALS: u32> Deserialize<'de> for StringOrDecimal<DECIMALS> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MyVisitor<const DECIMALS: u32>;
+
+        impl<'de, const DECIMALS: u32> Visitor<'de> for MyVisitor<DECIMALS> {
+            type Value = StringOrDecimal<DECIMALS>;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt.write_str("decimal number or string")
+            }
+
+            fn visit_f64<E>(self, val: f64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                // Display of f64 never uses an exponent, so this parses back exactly
+                Ok(StringOrDecimal(val, FixedPoint::parse(&val.to_string())))
+            }
+
+            fn visit_u64<E>(self, val: u64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(StringOrDecimal(
+                    val as f64,
+                    FixedPoint::parse(&val.to_string()),
+                ))
+            }
+
+            fn visit_i64<E>(self, val: i64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(StringOrDecimal(
+                    val as f64,
+                    FixedPoint::parse(&val.to_string()),
+                ))
+            }
+
+            fn visit_str<E>(self, val: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                match val.parse::<f64>() {
+                    Ok(float) => Ok(StringOrDecimal(float, FixedPoint::parse(val))),
+                    Err(_) => Err(E::custom(format!("failed to parse decimal from {:?}", val))),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(MyVisitor::<DECIMALS>)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VaultSet {
-    pub timestamp: String,
+    pub timestamp: StringOrU64,
     pub resultArray: Vec<SubgraphVault>,
-    pub price: StringOrF64,
-    pub rate: String,
-    pub liquidationRatio: String,
+    pub price: StringOrRay,
+    pub rate: StringOrRay,
+    pub liquidationRatio: StringOrRay,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SubgraphVault {
     pub id: String,
-    pub collateral: String,
-    pub debt: String,
+    pub collateral: StringOrWad,
+    pub debt: StringOrWad,
     pub cdpId: Option<String>,
     pub updatedAt: Option<String>,
     pub updatedAtBlock: Option<String>,
     pub updatedAtTransaction: Option<String>,
-    pub safetyLevel: String,
+    pub safetyLevel: StringOrF64,
 }
 
 #[derive(Debug, Serialize)]
@@ -99,31 +504,166 @@ pub struct Data<'a> {
     pub vaultsAtSecondBlock: &'a HashMap<String, VaultSet>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VaultTransitionStatus {
+    // present in both snapshots and in the vault history
+    Continuing,
+    // present at the first block only
+    Closed,
+    // present at the second block only
+    Opened,
+    // present in both snapshots but not in vaultHistory.json, so liquidation is unknown
+    MissingHistory,
+}
+
 #[derive(Debug, Serialize)]
 pub struct VaultTransitionInnerType<'a> {
-    pub first: &'a SubgraphVault,
-    pub second: &'a SubgraphVault,
+    // None for opened vaults
+    pub first: Option<&'a SubgraphVault>,
+    // None for closed vaults
+    pub second: Option<&'a SubgraphVault>,
+    pub status: VaultTransitionStatus,
     pub liquidated: bool,
     pub liquidationTimestamp: Option<u64>,
+    // sums over the liquidations between the blocks, None when not liquidated or when a
+    // liquidation log has no amount
+    pub liquidatedDebt: Option<StringOrWad>,
+    pub seizedCollateral: Option<StringOrWad>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BlockDiffMetadata {
     pub firstBlock: String,
-    pub firstTimestamp: String,
-    pub firstPrice: String,
-    pub firstRate: String,
-    pub firstLiquidationRatio: String,
+    pub firstTimestamp: u64,
+    pub firstPrice: StringOrRay,
+    pub firstRate: StringOrRay,
+    pub firstLiquidationRatio: StringOrRay,
 
     pub secondBlock: String,
-    pub secondTimestamp: String,
-    pub secondPrice: String,
-    pub secondRate: String,
-    pub secondLiquidationRatio: String,
+    pub secondTimestamp: u64,
+    pub secondPrice: StringOrRay,
+    pub secondRate: StringOrRay,
+    pub secondLiquidationRatio: StringOrRay,
 }
 
 #[derive(Debug, Serialize)]
 pub struct VaultTransitionWithMetadata<'a> {
     pub meta: BlockDiffMetadata,
-    pub vaultTransition: HashMap<&'a String, VaultTransitionInnerType<'a>>,
+    pub vaultTransition: BTreeMap<&'a String, VaultTransitionInnerType<'a>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wad(val: &str) -> Wad {
+        Wad::parse(val).unwrap()
+    }
+
+    #[test]
+    fn parse_reads_decimals_signs_and_exponents() {
+        assert_eq!(wad("1"), FixedPoint(I256::new(1_000_000_000_000_000_000)));
+        assert_eq!(
+            wad("-12.345"),
+            FixedPoint(I256::new(-12_345_000_000_000_000_000))
+        );
+        assert_eq!(wad("+.5"), FixedPoint(I256::new(500_000_000_000_000_000)));
+        assert_eq!(wad("1.2e-5"), FixedPoint(I256::new(12_000_000_000_000)));
+        assert_eq!(
+            wad("1.5E3"),
+            FixedPoint(I256::new(1_500_000_000_000_000_000_000))
+        );
+        assert_eq!(wad("0.000"), Wad::ZERO);
+    }
+
+    #[test]
+    fn parse_truncates_digits_beyond_the_decimals() {
+        assert_eq!(wad("0.0000000000000000019"), FixedPoint(I256::new(1)));
+        assert_eq!(wad("-0.0000000000000000019"), FixedPoint(I256::new(-1)));
+        assert_eq!(wad("1e-19"), Wad::ZERO);
+        assert_eq!(wad("123e-9223372036854775807"), Wad::ZERO);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_and_out_of_range_values() {
+        for val in [
+            "", "-", ".", "1.2.3", "1e", "e5", "abc", "1,5", "NaN", "inf", "--1",
+        ] {
+            assert_eq!(Wad::parse(val), None, "{:?}", val);
+        }
+        assert_eq!(Wad::parse("1e9223372036854775807"), None);
+        assert_eq!(Wad::parse("1e100"), None);
+    }
+
+    #[test]
+    fn checked_mul_rounds_half_away_from_zero() {
+        let tiny = FixedPoint::<18>(I256::new(5));
+        let half: Wad = wad("0.1");
+        // 5e-18 * 0.1 = 0.5e-18
+        assert_eq!(
+            tiny.checked_mul::<18, 18>(half),
+            Some(FixedPoint(I256::new(1)))
+        );
+        assert_eq!(
+            FixedPoint::<18>(I256::new(-5)).checked_mul::<18, 18>(half),
+            Some(FixedPoint(I256::new(-1)))
+        );
+        // 4e-18 * 0.1 = 0.4e-18
+        assert_eq!(
+            FixedPoint::<18>(I256::new(4)).checked_mul::<18, 18>(half),
+            Some(Wad::ZERO)
+        );
+    }
+
+    #[test]
+    fn checked_mul_keeps_every_digit_in_rad() {
+        let art = wad("1.000000000000000001");
+        let rate = Ray::parse("1.000000000000000000000000001").unwrap();
+        let product: Rad = art.checked_mul(rate).unwrap();
+        assert_eq!(
+            product,
+            Rad::parse("1.000000000000000001000000001000000000000000001").unwrap()
+        );
+    }
+
+    #[test]
+    fn checked_mul_and_checked_add_report_overflow() {
+        let max = FixedPoint::<18>(I256::MAX);
+        assert_eq!(max.checked_mul::<18, 18>(wad("2")), None);
+        assert_eq!(max.checked_add(FixedPoint(I256::ONE)), None);
+    }
+
+    #[test]
+    fn checked_div_rounds_towards_zero() {
+        let price = Ray::parse("2").unwrap();
+        let mat = Ray::parse("3").unwrap();
+        let spot: Ray = price.checked_div(mat).unwrap();
+        assert_eq!(spot, Ray::parse("0.666666666666666666666666666").unwrap());
+        assert_eq!(price.checked_div::<27, 27>(Ray::ZERO), None);
+    }
+
+    #[test]
+    fn display_prints_the_shortest_exact_form() {
+        assert_eq!(wad("1.050").to_string(), "1.05");
+        assert_eq!(wad("-0.5").to_string(), "-0.5");
+        assert_eq!(wad("42").to_string(), "42");
+        assert_eq!(Wad::ZERO.to_string(), "0");
+        assert_eq!(
+            FixedPoint::<18>(I256::new(1)).to_string(),
+            "0.000000000000000001"
+        );
+    }
+
+    #[test]
+    fn display_parses_back_to_the_same_value() {
+        for val in [
+            "0.1",
+            "-123.456789012345678",
+            "1000000",
+            "0.000000000000000001",
+        ] {
+            assert_eq!(wad(&wad(val).to_string()), wad(val));
+        }
+    }
 }
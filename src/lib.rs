@@ -1 +1,16 @@
+pub mod backtest;
+pub mod block_index;
+pub mod bootstrap;
+pub mod config;
+pub mod error;
 pub mod json_structure;
+pub mod liquidation_index;
+pub mod loader;
+pub mod optimizer;
+pub mod report;
+pub mod snapshot_cache;
+pub mod statistics;
+pub mod strategy;
+pub mod transition;
+pub mod validation;
+pub mod vault_filter;
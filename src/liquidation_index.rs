@@ -0,0 +1,177 @@
+#![allow(non_snake_case)]
+
+use crate::json_structure::{LiquidationStartLog, StringOrWad};
+use std::collections::HashMap;
+
+// one liquidationStartLog, the amounts are None when the history does not have them
+#[derive(Clone, Copy, Debug)]
+pub struct Liquidation {
+    pub timestamp: u64,
+    // debt taken from the vault
+    pub debt: Option<StringOrWad>,
+    // collateral seized from the vault
+    pub collateral: Option<StringOrWad>,
+}
+
+impl Liquidation {
+    pub fn at(timestamp: u64) -> Liquidation {
+        Liquidation {
+            timestamp,
+            debt: None,
+            collateral: None,
+        }
+    }
+}
+
+impl From<&LiquidationStartLog> for Liquidation {
+    fn from(log: &LiquidationStartLog) -> Liquidation {
+        Liquidation {
+            timestamp: log.info.timestamp.0,
+            debt: log.debt,
+            collateral: log.collateral,
+        }
+    }
+}
+
+// vault id -> liquidations, sorted by timestamp so lookups can binary search
+#[derive(Clone, Debug, Default)]
+pub struct LiquidationIndex {
+    liquidationsByVault: HashMap<String, Vec<Liquidation>>,
+}
+
+impl LiquidationIndex {
+    pub fn new() -> LiquidationIndex {
+        LiquidationIndex::default()
+    }
+
+    // replaces the liquidations of the vault
+    pub fn insert(&mut self, vault: String, mut liquidations: Vec<Liquidation>) {
+        liquidations.sort_by_key(|liquidation| liquidation.timestamp);
+        self.liquidationsByVault.insert(vault, liquidations);
+    }
+
+    // a vault without liquidations is still present, with no liquidations
+    pub fn contains(&self, vault: &str) -> bool {
+        self.liquidationsByVault.contains_key(vault)
+    }
+
+    // number of vaults
+    pub fn len(&self) -> usize {
+        self.liquidationsByVault.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.liquidationsByVault.is_empty()
+    }
+
+    // None when the vault is not in the history
+    pub fn liquidations(&self, vault: &str) -> Option<&[Liquidation]> {
+        self.liquidationsByVault
+            .get(vault)
+            .map(|liquidations| liquidations.as_slice())
+    }
+
+    // liquidations with t0 < timestamp < t1, in ascending order
+    pub fn liquidations_between(&self, vault: &str, t0: u64, t1: u64) -> &[Liquidation] {
+        let Some(liquidations) = self.liquidations(vault) else {
+            return &[];
+        };
+        let lower = liquidations.partition_point(|liquidation| liquidation.timestamp <= t0);
+        let upper = liquidations.partition_point(|liquidation| liquidation.timestamp < t1);
+        &liquidations[lower..upper.max(lower)]
+    }
+
+    pub fn count(&self, vault: &str) -> usize {
+        self.liquidations(vault)
+            .map_or(0, |liquidations| liquidations.len())
+    }
+
+    pub fn first(&self, vault: &str) -> Option<&Liquidation> {
+        self.liquidations(vault)?.first()
+    }
+
+    pub fn last(&self, vault: &str) -> Option<&Liquidation> {
+        self.liquidations(vault)?.last()
+    }
+}
+
+impl FromIterator<(String, Vec<Liquidation>)> for LiquidationIndex {
+    fn from_iter<I: IntoIterator<Item = (String, Vec<Liquidation>)>>(iter: I) -> LiquidationIndex {
+        let mut index = LiquidationIndex::new();
+        for (vault, liquidations) in iter {
+            index.insert(vault, liquidations);
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> LiquidationIndex {
+        [
+            (
+                "0x1-ETH-A".to_string(),
+                vec![
+                    Liquidation::at(300),
+                    Liquidation::at(100),
+                    Liquidation::at(200),
+                    Liquidation::at(200),
+                ],
+            ),
+            ("0x2-ETH-A".to_string(), vec![]),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn timestamps(liquidations: &[Liquidation]) -> Vec<u64> {
+        liquidations
+            .iter()
+            .map(|liquidation| liquidation.timestamp)
+            .collect()
+    }
+
+    #[test]
+    fn liquidations_are_sorted_by_timestamp() {
+        let index = index();
+        assert_eq!(
+            timestamps(index.liquidations("0x1-ETH-A").unwrap()),
+            [100, 200, 200, 300]
+        );
+        assert_eq!(index.first("0x1-ETH-A").unwrap().timestamp, 100);
+        assert_eq!(index.last("0x1-ETH-A").unwrap().timestamp, 300);
+        assert_eq!(index.count("0x1-ETH-A"), 4);
+    }
+
+    #[test]
+    fn liquidations_between_excludes_both_ends() {
+        let index = index();
+        let between = |t0, t1| timestamps(index.liquidations_between("0x1-ETH-A", t0, t1));
+        assert_eq!(between(100, 300), [200, 200]);
+        assert_eq!(between(99, 301), [100, 200, 200, 300]);
+        assert_eq!(between(100, 200), Vec::<u64>::new());
+        assert_eq!(between(199, 201), [200, 200]);
+        assert_eq!(between(200, 200), Vec::<u64>::new());
+        assert_eq!(between(300, 100), Vec::<u64>::new());
+        assert_eq!(between(0, u64::MAX), [100, 200, 200, 300]);
+    }
+
+    #[test]
+    fn vaults_without_liquidations_are_still_present() {
+        let index = index();
+        assert!(index.contains("0x2-ETH-A"));
+        assert_eq!(index.liquidations("0x2-ETH-A").map(|l| l.len()), Some(0));
+        assert!(index
+            .liquidations_between("0x2-ETH-A", 0, u64::MAX)
+            .is_empty());
+        assert!(!index.contains("0x3-ETH-A"));
+        assert_eq!(index.liquidations("0x3-ETH-A").map(|l| l.len()), None);
+        assert!(index
+            .liquidations_between("0x3-ETH-A", 0, u64::MAX)
+            .is_empty());
+        assert_eq!(index.count("0x3-ETH-A"), 0);
+        assert_eq!(index.len(), 2);
+    }
+}
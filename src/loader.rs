@@ -0,0 +1,598 @@
+#![allow(non_snake_case)]
+
+use crate::error::{message_without_position, Error, InvalidNumber};
+use crate::json_structure::{LiquidationStartLog, Vault, VaultSet};
+use crate::liquidation_index::{Liquidation, LiquidationIndex};
+use crate::snapshot_cache::{
+    read_snapshot_cache, snapshot_cache_path, write_snapshot_cache, CachedBlock, FileStamp,
+    SnapshotCache,
+};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_path_to_error::Segment;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+
+// block number -> ilk -> vault set
+pub type VaultSetsByBlock = HashMap<String, HashMap<String, VaultSet>>;
+
+// vault set fields parsed as numbers, a data error on these is reported as InvalidNumber
+const NUMERIC_FIELDS: [&str; 7] = [
+    "timestamp",
+    "price",
+    "rate",
+    "liquidationRatio",
+    "collateral",
+    "debt",
+    "safetyLevel",
+];
+
+pub fn read_vault_history_from_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<HashMap<String, Vault>, Error> {
+    let path = path.as_ref();
+    // Open the file in read-only mode with buffer.
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let reader = BufReader::new(file);
+
+    // Read the JSON contents of the file as an instance of `User`.
+    serde_json::from_reader(reader).map_err(|e| Error::json(path, &e))
+}
+
+// reads vaultHistory.json one vault at a time, keeping only the liquidationStartLogs of the
+// first vault of every id, so memory does not grow with the other logs
+pub fn read_liquidation_index_from_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<LiquidationIndex, Error> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let reader = BufReader::new(file);
+
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer
+        .deserialize_map(LiquidationIndexVisitor)
+        .and_then(|index| deserializer.end().map(|_| index))
+        .map_err(|e| Error::json(path, &e))
+}
+
+struct LiquidationIndexVisitor;
+
+impl<'de> Visitor<'de> for LiquidationIndexVisitor {
+    type Value = LiquidationIndex;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("map of vault id to vault history")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut index = LiquidationIndex::new();
+        while let Some(vaultId) = map.next_key::<String>()? {
+            let history: VaultLiquidations = map.next_value()?;
+            index.insert(vaultId, history.vaults.0);
+        }
+        Ok(index)
+    }
+}
+
+// `{"vaults": [{"cdpId": ..., "logs": [...]}, ...]}`, other fields are skipped
+#[derive(Deserialize)]
+struct VaultLiquidations {
+    vaults: FirstVaultLiquidations,
+}
+
+#[derive(Deserialize)]
+struct VaultWithLiquidations {
+    logs: VaultLiquidationList,
+}
+
+// liquidations of the first vault, the others are skipped as before
+struct FirstVaultLiquidations(Vec<Liquidation>);
+
+impl<'de> Deserialize<'de> for FirstVaultLiquidations {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MyVisitor;
+
+        impl<'de> Visitor<'de> for MyVisitor {
+            type Value = FirstVaultLiquidations;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt.write_str("list of vaults")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let liquidations = match seq.next_element::<VaultWithLiquidations>()? {
+                    Some(vault) => vault.logs.0,
+                    None => vec![],
+                };
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(FirstVaultLiquidations(liquidations))
+            }
+        }
+
+        deserializer.deserialize_seq(MyVisitor)
+    }
+}
+
+struct VaultLiquidationList(Vec<Liquidation>);
+
+impl<'de> Deserialize<'de> for VaultLiquidationList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MyVisitor;
+
+        impl<'de> Visitor<'de> for MyVisitor {
+            type Value = VaultLiquidationList;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt.write_str("list of vault logs")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut liquidations: Vec<Liquidation> = vec![];
+                while let Some(log) = seq.next_element::<LiquidationLog>()? {
+                    if let Some(log) = log.0 {
+                        liquidations.push(Liquidation::from(&log));
+                    }
+                }
+                Ok(VaultLiquidationList(liquidations))
+            }
+        }
+
+        deserializer.deserialize_seq(MyVisitor)
+    }
+}
+
+// one vault log, only decoded when it is a liquidationStartLog so a malformed log of
+// another type does not fail the load
+struct LiquidationLog(Option<LiquidationStartLog>);
+
+const LIQUIDATION_START_LOG: &str = "liquidationStartLog";
+
+impl<'de> Deserialize<'de> for LiquidationLog {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MyVisitor;
+
+        impl<'de> Visitor<'de> for MyVisitor {
+            type Value = LiquidationLog;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt.write_str("vault log")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let Some(key) = map.next_key::<String>()? else {
+                    return Ok(LiquidationLog(None));
+                };
+                if key != "__typename" {
+                    // __typename comes later, keep the log until its type is known
+                    let mut log = serde_json::Map::new();
+                    log.insert(key, map.next_value()?);
+                    while let Some((key, value)) = map.next_entry()? {
+                        log.insert(key, value);
+                    }
+                    if log.get("__typename").and_then(|typename| typename.as_str())
+                        != Some(LIQUIDATION_START_LOG)
+                    {
+                        return Ok(LiquidationLog(None));
+                    }
+                    return serde_json::from_value(serde_json::Value::Object(log))
+                        .map(|log| LiquidationLog(Some(log)))
+                        .map_err(serde::de::Error::custom);
+                }
+                if map.next_value::<String>()? != LIQUIDATION_START_LOG {
+                    while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+                    return Ok(LiquidationLog(None));
+                }
+                LiquidationStartLog::deserialize(MapAccessDeserializer::new(map))
+                    .map(|log| LiquidationLog(Some(log)))
+            }
+        }
+
+        deserializer.deserialize_map(MyVisitor)
+    }
+}
+
+pub fn read_vault_set_from_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<HashMap<String, VaultSet>, Error> {
+    let path = path.as_ref();
+    // Open the file in read-only mode with buffer.
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let reader = BufReader::new(file);
+
+    // Read the JSON contents of the file, keeping track of where a malformed value was found.
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|e| vault_set_error(path, &e))
+}
+
+// numeric fields become InvalidNumber with the block, ilk and vault they belong to,
+// e.g. `block 16266198, ilk ETH-A, vault 0x1234-ETH-A: invalid number at ETH-A.resultArray[3].debt`
+fn vault_set_error(path: &Path, e: &serde_path_to_error::Error<serde_json::Error>) -> Error {
+    let inner = e.inner();
+    let segments: Vec<&Segment> = e.path().iter().collect();
+    let isNumericField = matches!(
+        segments.last(),
+        Some(Segment::Map { key }) if NUMERIC_FIELDS.contains(&key.as_str())
+    );
+    if !inner.is_data() || !isNumericField {
+        return Error::json(path, inner);
+    }
+
+    let block = path
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .map_or(String::new(), |name| name.to_string_lossy().to_string());
+    let mut ilk: Option<String> = None;
+    let mut vault: Option<String> = None;
+    if let Some(Segment::Map { key }) = segments.first() {
+        ilk = Some(key.to_string());
+        if let (Some(Segment::Map { key: field }), Some(Segment::Seq { index })) =
+            (segments.get(1), segments.get(2))
+        {
+            if field == "resultArray" {
+                vault = Some(
+                    vault_id_at(path, key, *index)
+                        .unwrap_or_else(|| format!("resultArray[{}]", index)),
+                );
+            }
+        }
+    }
+    Error::InvalidNumber(Box::new(InvalidNumber {
+        path: path.to_path_buf(),
+        block,
+        ilk,
+        vault,
+        field: e.path().to_string(),
+        line: inner.line(),
+        column: inner.column(),
+        message: message_without_position(inner),
+    }))
+}
+
+// only used to report errors, so re-reading the file is fine
+fn vault_id_at(path: &Path, ilk: &str, index: usize) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let value: serde_json::Value = serde_json::from_reader(BufReader::new(file)).ok()?;
+    value[ilk]["resultArray"][index]["id"]
+        .as_str()
+        .map(|id| id.to_string())
+}
+
+// the json file inside a single `vaultSet/<block>/` directory
+fn block_file(path: &Path) -> Result<Option<PathBuf>, Error> {
+    let inner_dir = fs::read_dir(path).map_err(|e| Error::io(path, e))?;
+    let mut json_file: Option<PathBuf> = None;
+    for inner_item in inner_dir {
+        json_file = Some(inner_item.map_err(|e| Error::io(path, e))?.path());
+    }
+    Ok(json_file)
+}
+
+// reads the json file inside a single `vaultSet/<block>/` directory
+pub fn read_block_dir<P: AsRef<Path>>(path: P) -> Result<Option<HashMap<String, VaultSet>>, Error> {
+    match block_file(path.as_ref())? {
+        Some(x) => Ok(Some(read_vault_set_from_file(x)?)),
+        None => Ok(None),
+    }
+}
+
+// how much of a directory read came from the snapshot cache
+#[derive(Debug, Default)]
+pub struct CacheUsage {
+    // None when there is no cache file or it was bypassed
+    pub cachePath: Option<PathBuf>,
+    pub cachedBlocks: usize,
+    pub blocks: usize,
+    // why an existing cache file was ignored
+    pub ignored: Option<Error>,
+}
+
+// blocks of `<path>.cache` by block number, a cache that can't be read is ignored and
+// recorded in `usage`
+fn read_cached_blocks(path: &str, usage: &mut CacheUsage) -> Option<HashMap<String, CachedBlock>> {
+    let cachePath = snapshot_cache_path(path);
+    if !cachePath.exists() {
+        return None;
+    }
+    match read_snapshot_cache(&cachePath) {
+        Ok(cache) => {
+            usage.cachePath = Some(cachePath);
+            Some(
+                cache
+                    .blocks
+                    .into_iter()
+                    .map(|cachedBlock| (cachedBlock.block.clone(), cachedBlock))
+                    .collect(),
+            )
+        }
+        Err(e) => {
+            usage.ignored = Some(e);
+            None
+        }
+    }
+}
+
+// reads `vaultSet/<block>/` directories, blocks found in `<path>.cache` with an unchanged
+// json file (same size and modification time) are taken from the cache
+pub fn read_dir(
+    path: &str,
+    allVaultsAtBlock: &mut HashMap<String, HashMap<String, VaultSet>>,
+) -> Result<CacheUsage, Error> {
+    read_dir_with_cache(path, allVaultsAtBlock, true)
+}
+
+// same as read_dir, but always parses the json files
+pub fn read_json_dir(
+    path: &str,
+    allVaultsAtBlock: &mut HashMap<String, HashMap<String, VaultSet>>,
+) -> Result<CacheUsage, Error> {
+    read_dir_with_cache(path, allVaultsAtBlock, false)
+}
+
+fn read_dir_with_cache(
+    path: &str,
+    allVaultsAtBlock: &mut HashMap<String, HashMap<String, VaultSet>>,
+    useCache: bool,
+) -> Result<CacheUsage, Error> {
+    let mut usage = CacheUsage::default();
+    let mut cached = if useCache {
+        read_cached_blocks(path, &mut usage)
+    } else {
+        None
+    };
+    let dir = fs::read_dir(path).map_err(|e| Error::io(path, e))?;
+    // take 10 is for debug
+    for item in dir
+    // .take(10)
+    {
+        let item = item.map_err(|e| Error::io(path, e))?;
+        // directory names that are not valid UTF-8 can't be block numbers
+        let Ok(block_number_str) = item.file_name().into_string() else {
+            continue;
+        };
+        let Some(file) = block_file(&item.path())? else {
+            continue;
+        };
+        usage.blocks += 1;
+        let cachedBlock = cached
+            .as_mut()
+            .and_then(|blocks| blocks.remove(&block_number_str));
+        if let Some(cachedBlock) = cachedBlock {
+            if FileStamp::of(&file)?.as_ref() == Some(&cachedBlock.stamp) {
+                allVaultsAtBlock.insert(block_number_str, cachedBlock.into_vault_sets());
+                usage.cachedBlocks += 1;
+                continue;
+            }
+        }
+        allVaultsAtBlock.insert(block_number_str, read_vault_set_from_file(file)?);
+    }
+    Ok(usage)
+}
+
+// stamp of the json file of every `vaultSet/<block>/` directory, by block
+pub fn block_stamps(path: &str) -> Result<BTreeMap<String, Option<FileStamp>>, Error> {
+    let mut stamps: BTreeMap<String, Option<FileStamp>> = BTreeMap::new();
+    let dir = fs::read_dir(path).map_err(|e| Error::io(path, e))?;
+    for item in dir {
+        let item = item.map_err(|e| Error::io(path, e))?;
+        let Ok(block_number_str) = item.file_name().into_string() else {
+            continue;
+        };
+        let Some(file) = block_file(&item.path())? else {
+            continue;
+        };
+        stamps.insert(block_number_str, FileStamp::of(&file)?);
+    }
+    Ok(stamps)
+}
+
+// parses every block of the directory into `<path>.cache`, returns the cache path and the
+// number of cached blocks
+pub fn build_snapshot_cache(path: &str) -> Result<(PathBuf, usize), Error> {
+    let mut cache = SnapshotCache::default();
+    let dir = fs::read_dir(path).map_err(|e| Error::io(path, e))?;
+    for item in dir {
+        let item = item.map_err(|e| Error::io(path, e))?;
+        let Ok(block_number_str) = item.file_name().into_string() else {
+            continue;
+        };
+        let Some(file) = block_file(&item.path())? else {
+            continue;
+        };
+        // without a modification time the block could never be validated
+        let Some(stamp) = FileStamp::of(&file)? else {
+            continue;
+        };
+        let vaultSets = read_vault_set_from_file(&file)?;
+        cache
+            .blocks
+            .push(CachedBlock::new(block_number_str, stamp, &vaultSets));
+    }
+    cache.blocks.sort_by(|a, b| a.block.cmp(&b.block));
+    let cachePath = snapshot_cache_path(path);
+    write_snapshot_cache(&cachePath, &cache)?;
+    Ok((cachePath, cache.blocks.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtest::build_liquidation_index;
+
+    const VAULT_HISTORY: &str = r#"{
+        "0x1-ETH-A": {"vaults": [
+            {"cdpId": "1", "logs": [
+                {"__typename": "creationLog", "timestamp": "50"},
+                {"__typename": "liquidationStartLog", "timestamp": "300", "debt": "10.5", "collateral": "2"},
+                {"timestamp": "100", "block": "16000000", "__typename": "liquidationStartLog"},
+                {"__typename": "debtChangeLog", "timestamp": "120", "debtDiff": "-1"}
+            ]},
+            {"cdpId": "1", "logs": [
+                {"__typename": "liquidationStartLog", "timestamp": "999"}
+            ]}
+        ]},
+        "0x2-ETH-A": {"vaults": [{"cdpId": "2", "logs": []}]},
+        "0x3-ETH-A": {"vaults": []},
+        "0x4-ETH-A": {"vaults": [{"cdpId": "4", "logs": [
+            {"__typename": "somethingNew", "timestamp": "1"},
+            {"__typename": "liquidationStartLog", "timestamp": "400", "auctionId": "7"}
+        ]}]}
+    }"#;
+
+    fn write_temp(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn summary(index: &LiquidationIndex, vault: &str) -> Option<Vec<(u64, Option<String>)>> {
+        Some(
+            index
+                .liquidations(vault)?
+                .iter()
+                .map(|liquidation| {
+                    (
+                        liquidation.timestamp,
+                        liquidation.debt.map(|debt| debt.to_string()),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn streaming_index_matches_the_index_of_the_full_history() {
+        let path = write_temp("vault-history", VAULT_HISTORY);
+        let streamed = read_liquidation_index_from_file(&path).unwrap();
+        let full = build_liquidation_index(&read_vault_history_from_file(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(streamed.len(), full.len());
+        for vault in [
+            "0x1-ETH-A",
+            "0x2-ETH-A",
+            "0x3-ETH-A",
+            "0x4-ETH-A",
+            "0x5-ETH-A",
+        ] {
+            assert_eq!(
+                summary(&streamed, vault),
+                summary(&full, vault),
+                "{}",
+                vault
+            );
+        }
+        assert_eq!(
+            summary(&streamed, "0x1-ETH-A"),
+            Some(vec![(100, None), (300, Some("10.5".to_string()))])
+        );
+        assert_eq!(summary(&streamed, "0x3-ETH-A"), Some(vec![]));
+    }
+
+    #[test]
+    fn streaming_index_skips_malformed_logs_of_other_types() {
+        let path = write_temp(
+            "vault-history-malformed",
+            r#"{"0x1-ETH-A": {"vaults": [{"cdpId": "1", "logs": [
+                {"__typename": "creationLog", "timestamp": "x"},
+                {"timestamp": [], "__typename": "collateralChangeLog"},
+                {"__typename": "liquidationStartLog", "timestamp": "100"}
+            ]}]}}"#,
+        );
+        let index = read_liquidation_index_from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            summary(&index.unwrap(), "0x1-ETH-A"),
+            Some(vec![(100, None)])
+        );
+    }
+
+    #[test]
+    fn streaming_index_rejects_malformed_liquidations() {
+        let path = write_temp(
+            "vault-history-bad-liquidation",
+            r#"{"0x1-ETH-A": {"vaults": [{"cdpId": "1", "logs": [
+                {"__typename": "liquidationStartLog", "timestamp": "x"}
+            ]}]}}"#,
+        );
+        let index = read_liquidation_index_from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(index.is_err());
+    }
+
+    fn vault_set_json(debt: &str) -> String {
+        format!(
+            r#"{{"ETH-A": {{"timestamp": "1000", "price": "2000", "rate": "1",
+                "liquidationRatio": "1.5", "resultArray": [{{"id": "0x1-ETH-A",
+                "collateral": "10", "debt": "{}", "cdpId": null, "updatedAt": null,
+                "updatedAtBlock": null, "updatedAtTransaction": null,
+                "safetyLevel": "150"}}]}}}}"#,
+            debt
+        )
+    }
+
+    #[test]
+    fn read_dir_parses_blocks_whose_file_changed_since_the_cache_was_built() {
+        let root = std::env::temp_dir().join(format!("vault-set-cache-{}", std::process::id()));
+        let vaultSet = root.join("vaultSet");
+        for block in ["100", "200"] {
+            fs::create_dir_all(vaultSet.join(block)).unwrap();
+            fs::write(
+                vaultSet.join(block).join("vaultSet.json"),
+                vault_set_json("100"),
+            )
+            .unwrap();
+        }
+        let path = vaultSet.to_str().unwrap();
+        let (cachePath, cachedBlocks) = build_snapshot_cache(path).unwrap();
+        assert_eq!(cachedBlocks, 2);
+
+        let mut allVaultsAtBlock: VaultSetsByBlock = HashMap::new();
+        let usage = read_dir(path, &mut allVaultsAtBlock).unwrap();
+        assert_eq!((usage.cachedBlocks, usage.blocks), (2, 2));
+        assert_eq!(usage.cachePath.as_ref(), Some(&cachePath));
+
+        fs::write(
+            vaultSet.join("200").join("vaultSet.json"),
+            vault_set_json("250.5"),
+        )
+        .unwrap();
+        let mut allVaultsAtBlock: VaultSetsByBlock = HashMap::new();
+        let usage = read_dir(path, &mut allVaultsAtBlock).unwrap();
+        let debt = |block: &str| allVaultsAtBlock[block]["ETH-A"].resultArray[0].debt.0;
+        assert_eq!((usage.cachedBlocks, usage.blocks), (1, 2));
+        assert_eq!((debt("100"), debt("200")), (100.0, 250.5));
+
+        let mut allVaultsAtBlock: VaultSetsByBlock = HashMap::new();
+        let usage = read_json_dir(path, &mut allVaultsAtBlock).unwrap();
+        assert_eq!((usage.cachedBlocks, usage.cachePath), (0, None));
+        // the cache file is next to vaultSet, inside root
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,654 @@
+#![allow(non_snake_case)]
+
+use crate::backtest::{evaluate_strategies, BacktestReport, DRatioReport, EvaluationOptions};
+use crate::bootstrap::Rng;
+use crate::error::Error;
+use crate::strategy::{
+    LinearRampStrategy, LiquidationStrategy, LogisticStrategy, ThresholdStrategy,
+};
+use crate::transition::IlkTransitions;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::slice;
+use std::str::FromStr;
+
+// weight of underestimated pairs in the asymmetric objective when none is given
+pub const DEFAULT_UNDERESTIMATION_WEIGHT: f64 = 2.0;
+
+// what the optimizer minimizes, computed from the aggregate report over all selected ilks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Objective {
+    // mean |dRatio|
+    #[default]
+    Mean,
+    // median |dRatio|
+    Median,
+    // sqrt(mean dRatio^2)
+    Rmse,
+    // mean |dRatio| with underestimated pairs (capitalAtRiskValueLiq > capitalAtRiskValueRisk)
+    // weighted by the underestimation weight
+    Asymmetric,
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Objective, String> {
+        match s {
+            "mean" => Ok(Objective::Mean),
+            "median" => Ok(Objective::Median),
+            "rmse" => Ok(Objective::Rmse),
+            "asymmetric" => Ok(Objective::Asymmetric),
+            _ => Err(format!(
+                "unknown objective {:?}, expected mean, median, rmse or asymmetric",
+                s
+            )),
+        }
+    }
+}
+
+impl Objective {
+    // infinite without valid data points, so such parameters never win
+    pub fn value(self, report: &DRatioReport, underestimationWeight: f64) -> f64 {
+        let value = match self {
+            Objective::Mean => report.dRatioStatistics.mae.unwrap_or(f64::NAN),
+            Objective::Median => report.dRatioMedian,
+            Objective::Rmse => report.dRatioStatistics.rmse.unwrap_or(f64::NAN),
+            // plusSum holds the positive dRatios and minusSum the others
+            Objective::Asymmetric => {
+                (underestimationWeight * report.plusSum - report.minusSum)
+                    / report.validDataPointCount as f64
+            }
+        };
+        if value.is_nan() {
+            f64::INFINITY
+        } else {
+            value
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    // a grid over the bounds, then grids around the best point so far
+    #[default]
+    Grid,
+    NelderMead,
+    // samples drawn uniformly within the bounds
+    Random,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Method, String> {
+        match s {
+            "grid" => Ok(Method::Grid),
+            "nelder-mead" => Ok(Method::NelderMead),
+            "random" => Ok(Method::Random),
+            _ => Err(format!(
+                "unknown method {:?}, expected grid, nelder-mead or random",
+                s
+            )),
+        }
+    }
+}
+
+// strategies whose parameters are searched
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StrategyFamily {
+    #[default]
+    Threshold,
+    Linear,
+    Logistic,
+}
+
+impl FromStr for StrategyFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StrategyFamily, String> {
+        match s {
+            "threshold" => Ok(StrategyFamily::Threshold),
+            "linear" => Ok(StrategyFamily::Linear),
+            "logistic" => Ok(StrategyFamily::Logistic),
+            _ => Err(format!(
+                "unknown strategy {:?}, expected threshold, linear or logistic",
+                s
+            )),
+        }
+    }
+}
+
+impl StrategyFamily {
+    pub fn parameter_names(self) -> &'static [&'static str] {
+        match self {
+            StrategyFamily::Threshold => &["threshold", "coefficient"],
+            StrategyFamily::Linear => &["lower", "upper", "coefficient"],
+            StrategyFamily::Logistic => &["midpoint", "steepness", "coefficient"],
+        }
+    }
+
+    // the default bounds of every parameter, replaced by the overrides with the same name
+    pub fn bounds(self, overrides: &[ParameterBound]) -> Result<Vec<ParameterBound>, String> {
+        if let Some(unknown) = overrides
+            .iter()
+            .find(|bound| !self.parameter_names().contains(&bound.name.as_str()))
+        {
+            return Err(format!(
+                "unknown parameter {:?}, expected one of {}",
+                unknown.name,
+                self.parameter_names().join(", ")
+            ));
+        }
+        let defaults: &[(f64, f64)] = match self {
+            StrategyFamily::Threshold => &[(25.0, 300.0), (0.0, 1.0)],
+            StrategyFamily::Linear => &[(0.0, 300.0), (25.0, 600.0), (0.0, 1.0)],
+            StrategyFamily::Logistic => &[(25.0, 300.0), (0.001, 0.5), (0.0, 1.0)],
+        };
+        Ok(self
+            .parameter_names()
+            .iter()
+            .zip(defaults)
+            .map(|(name, (lower, upper))| {
+                overrides
+                    .iter()
+                    .rev()
+                    .find(|bound| bound.name == *name)
+                    .cloned()
+                    .unwrap_or(ParameterBound {
+                        name: name.to_string(),
+                        lower: *lower,
+                        upper: *upper,
+                    })
+            })
+            .collect())
+    }
+
+    // parameters in parameter_names order, None when the family does not accept them
+    pub fn strategy(self, parameters: &[f64]) -> Option<Box<dyn LiquidationStrategy>> {
+        match (self, parameters) {
+            (StrategyFamily::Threshold, &[threshold, coefficient]) => {
+                Some(Box::new(ThresholdStrategy {
+                    threshold,
+                    coefficient,
+                }))
+            }
+            (StrategyFamily::Linear, &[lower, upper, coefficient]) if lower < upper => {
+                Some(Box::new(LinearRampStrategy {
+                    lower,
+                    upper,
+                    coefficient,
+                }))
+            }
+            (StrategyFamily::Logistic, &[midpoint, steepness, coefficient]) => {
+                Some(Box::new(LogisticStrategy {
+                    midpoint,
+                    steepness,
+                    coefficient,
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+// inclusive range of one parameter, written as "threshold=25:300"
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ParameterBound {
+    pub name: String,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl FromStr for ParameterBound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ParameterBound, String> {
+        let invalid = || format!("invalid bound {:?}, expected name=lower:upper", s);
+        let (name, range) = s.split_once('=').ok_or_else(invalid)?;
+        let (lower, upper) = range.split_once(':').ok_or_else(invalid)?;
+        Ok(ParameterBound {
+            name: name.trim().to_string(),
+            lower: lower.trim().parse().map_err(|_| invalid())?,
+            upper: upper.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OptimizerOptions {
+    pub family: StrategyFamily,
+    // one per parameter of the family, in parameter_names order
+    pub bounds: Vec<ParameterBound>,
+    pub method: Method,
+    pub objective: Objective,
+    // weight of underestimated pairs in the asymmetric objective
+    pub underestimationWeight: f64,
+    // grid: values per parameter and number of rounds around the best point
+    pub gridPoints: usize,
+    pub refinements: usize,
+    // nelder-mead: stops after maxIterations, or once the objective values and the
+    // parameters (scaled to [0, 1]) of the simplex are within tolerance of each other
+    pub maxIterations: usize,
+    pub tolerance: f64,
+    // random: samples and the seed they are drawn with
+    pub samples: usize,
+    pub seed: u64,
+}
+
+impl OptimizerOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bounds.len() != self.family.parameter_names().len() {
+            return Err("optimize: one bound per parameter is required".to_string());
+        }
+        for bound in &self.bounds {
+            if !(bound.lower.is_finite() && bound.upper.is_finite()) {
+                return Err(format!("optimize: bounds of {} must be finite", bound.name));
+            }
+            if bound.lower > bound.upper {
+                return Err(format!(
+                    "optimize: lower bound of {} is greater than its upper bound",
+                    bound.name
+                ));
+            }
+        }
+        if !(self.underestimationWeight.is_finite() && self.underestimationWeight > 0.0) {
+            return Err("optimize: underestimation weight must be positive".to_string());
+        }
+        if self.gridPoints < 2 {
+            return Err("optimize: at least two grid points are required".to_string());
+        }
+        if self.maxIterations == 0 || self.samples == 0 {
+            return Err("optimize: iterations and samples must be positive".to_string());
+        }
+        if self.tolerance.is_nan() || self.tolerance < 0.0 {
+            return Err("optimize: tolerance must not be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+// one evaluated parameter set
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchStep {
+    // 1-based, in evaluation order
+    pub evaluation: usize,
+    // refinement round of the grid or nelder-mead iteration, 0 for the initial grid or
+    // simplex and for random search
+    pub round: usize,
+    pub strategy: String,
+    // in parameter_names order
+    pub parameters: Vec<f64>,
+    pub objective: f64,
+    pub dRatioMean: f64,
+    pub dRatioMedian: f64,
+    pub validDataPointCount: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchResult {
+    pub family: StrategyFamily,
+    pub parameterNames: Vec<&'static str>,
+    pub bounds: Vec<ParameterBound>,
+    pub method: Method,
+    pub objective: Objective,
+    // the first evaluation with the lowest objective
+    pub best: SearchStep,
+    pub bestReport: BacktestReport,
+    // every distinct parameter set the family accepts, in evaluation order
+    pub trace: Vec<SearchStep>,
+}
+
+struct Search<'a> {
+    ilkTransitions: &'a [IlkTransitions],
+    evaluationOptions: &'a EvaluationOptions,
+    options: &'a OptimizerOptions,
+    // objective by parameter bits, so revisited points are not evaluated again
+    seen: HashMap<Vec<u64>, f64>,
+    trace: Vec<SearchStep>,
+    best: Option<(SearchStep, BacktestReport)>,
+}
+
+fn bits(parameters: &[f64]) -> Vec<u64> {
+    parameters.iter().map(|x| x.to_bits()).collect()
+}
+
+impl Search<'_> {
+    // objective of every point, new points are evaluated in parallel
+    fn evaluate(&mut self, points: &[Vec<f64>], round: usize) -> Result<Vec<f64>, Error> {
+        let mut parameters: Vec<Vec<f64>> = vec![];
+        let mut strategies: Vec<Box<dyn LiquidationStrategy>> = vec![];
+        for point in points {
+            let key = bits(point);
+            if self.seen.contains_key(&key) || parameters.iter().any(|p| bits(p) == key) {
+                continue;
+            }
+            match self.options.family.strategy(point) {
+                Some(strategy) => {
+                    parameters.push(point.clone());
+                    strategies.push(strategy);
+                }
+                None => {
+                    self.seen.insert(key, f64::INFINITY);
+                }
+            }
+        }
+        if !strategies.is_empty() {
+            let reports = evaluate_strategies(
+                self.ilkTransitions,
+                &strategies,
+                self.evaluationOptions,
+                None,
+            )?;
+            for ((point, strategy), report) in parameters.into_iter().zip(&strategies).zip(reports)
+            {
+                let aggregate = &report.aggregate;
+                let objective = self
+                    .options
+                    .objective
+                    .value(aggregate, self.options.underestimationWeight);
+                self.seen.insert(bits(&point), objective);
+                let step = SearchStep {
+                    evaluation: self.trace.len() + 1,
+                    round,
+                    strategy: strategy.label(),
+                    parameters: point,
+                    objective,
+                    dRatioMean: aggregate.dRatioMean,
+                    dRatioMedian: aggregate.dRatioMedian,
+                    validDataPointCount: aggregate.validDataPointCount,
+                };
+                if objective.is_finite()
+                    && self
+                        .best
+                        .as_ref()
+                        .is_none_or(|(best, _)| objective < best.objective)
+                {
+                    self.best = Some((step.clone(), report));
+                }
+                self.trace.push(step);
+            }
+        }
+        Ok(points.iter().map(|point| self.seen[&bits(point)]).collect())
+    }
+
+    fn grid(&mut self) -> Result<(), Error> {
+        let gridPoints = self.options.gridPoints;
+        let mut bounds: Vec<(f64, f64)> = self
+            .options
+            .bounds
+            .iter()
+            .map(|bound| (bound.lower, bound.upper))
+            .collect();
+        for round in 0..=self.options.refinements {
+            let axes: Vec<Vec<f64>> = bounds
+                .iter()
+                .map(|(lower, upper)| {
+                    (0..gridPoints)
+                        .map(|i| {
+                            let x = lower + (upper - lower) * i as f64 / (gridPoints - 1) as f64;
+                            // so 0.1 + 0.2 prints as 0.3
+                            (x * 1e12).round() / 1e12
+                        })
+                        .collect()
+                })
+                .collect();
+            let mut points: Vec<Vec<f64>> = vec![vec![]];
+            for axis in &axes {
+                points = points
+                    .iter()
+                    .flat_map(|point| {
+                        axis.iter().map(move |x| {
+                            let mut point = point.clone();
+                            point.push(*x);
+                            point
+                        })
+                    })
+                    .collect();
+            }
+            self.evaluate(&points, round)?;
+
+            // the next round spans one grid step either side of the best point
+            let Some((best, _)) = &self.best else {
+                break;
+            };
+            bounds = bounds
+                .iter()
+                .zip(&best.parameters)
+                .zip(&self.options.bounds)
+                .map(|(((lower, upper), x), bound)| {
+                    let step = (upper - lower) / (gridPoints - 1) as f64;
+                    ((x - step).max(bound.lower), (x + step).min(bound.upper))
+                })
+                .collect();
+        }
+        Ok(())
+    }
+
+    // works on parameters scaled to [0, 1] so every bound weighs the same
+    fn nelder_mead(&mut self) -> Result<(), Error> {
+        let options = self.options;
+        let bounds = &options.bounds;
+        let scale = |x: &[f64]| -> Vec<f64> {
+            x.iter()
+                .zip(bounds)
+                .map(|(x, bound)| bound.lower + x * (bound.upper - bound.lower))
+                .collect()
+        };
+        nelder_mead(
+            bounds.len(),
+            options.maxIterations,
+            options.tolerance,
+            |points, iteration| {
+                let points: Vec<Vec<f64>> = points.iter().map(|x| scale(x)).collect();
+                self.evaluate(&points, iteration)
+            },
+        )?;
+        Ok(())
+    }
+
+    fn random(&mut self) -> Result<(), Error> {
+        let mut rng = Rng(self.options.seed);
+        let points: Vec<Vec<f64>> = (0..self.options.samples)
+            .map(|_| {
+                self.options
+                    .bounds
+                    .iter()
+                    .map(|bound| bound.lower + rng.unit() * (bound.upper - bound.lower))
+                    .collect()
+            })
+            .collect();
+        self.evaluate(&points, 0)?;
+        Ok(())
+    }
+}
+
+// minimizes `objective` over the unit cube of n dimensions, points outside the cube are
+// moved onto it; `objective` gets the points and the iteration (0 for the initial simplex)
+// and returns their values; the best vertex is returned
+fn nelder_mead<F>(
+    n: usize,
+    maxIterations: usize,
+    tolerance: f64,
+    mut objective: F,
+) -> Result<Vec<f64>, Error>
+where
+    F: FnMut(&[Vec<f64>], usize) -> Result<Vec<f64>, Error>,
+{
+    let mut simplex: Vec<Vec<f64>> = (0..=n)
+        .map(|i| {
+            let mut x = vec![0.5; n];
+            if i > 0 {
+                x[i - 1] = 0.75;
+            }
+            x
+        })
+        .collect();
+    let mut values = objective(&simplex, 0)?;
+
+    for iteration in 1..=maxIterations {
+        let mut order: Vec<usize> = (0..=n).collect();
+        order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+        simplex = order.iter().map(|i| simplex[*i].clone()).collect();
+        values = order.iter().map(|i| values[*i]).collect();
+
+        let size = simplex[1..]
+            .iter()
+            .flat_map(|x| x.iter().zip(&simplex[0]).map(|(a, b)| (a - b).abs()))
+            .fold(0.0, f64::max);
+        if (values[n] - values[0]).abs() <= tolerance && size <= tolerance {
+            break;
+        }
+
+        let centroid: Vec<f64> = (0..n)
+            .map(|d| simplex[..n].iter().map(|x| x[d]).sum::<f64>() / n as f64)
+            .collect();
+        let worst = simplex[n].clone();
+        let along = |t: f64| -> Vec<f64> {
+            centroid
+                .iter()
+                .zip(&worst)
+                .map(|(c, w)| (c + t * (c - w)).clamp(0.0, 1.0))
+                .collect()
+        };
+
+        let reflected = along(1.0);
+        let reflectedValue = objective(slice::from_ref(&reflected), iteration)?[0];
+        if reflectedValue < values[0] {
+            let expanded = along(2.0);
+            let expandedValue = objective(slice::from_ref(&expanded), iteration)?[0];
+            if expandedValue < reflectedValue {
+                (simplex[n], values[n]) = (expanded, expandedValue);
+            } else {
+                (simplex[n], values[n]) = (reflected, reflectedValue);
+            }
+        } else if reflectedValue < values[n - 1] {
+            (simplex[n], values[n]) = (reflected, reflectedValue);
+        } else {
+            // outside contraction when the reflection improved on the worst point
+            let contracted = if reflectedValue < values[n] {
+                along(0.5)
+            } else {
+                along(-0.5)
+            };
+            let contractedValue = objective(slice::from_ref(&contracted), iteration)?[0];
+            if contractedValue < reflectedValue.min(values[n]) {
+                (simplex[n], values[n]) = (contracted, contractedValue);
+            } else {
+                // shrink towards the best point
+                let best = simplex[0].clone();
+                for x in simplex[1..].iter_mut() {
+                    for (x, b) in x.iter_mut().zip(&best) {
+                        *x = b + 0.5 * (*x - b);
+                    }
+                }
+                let shrunkValues = objective(&simplex[1..], iteration)?;
+                values[1..].copy_from_slice(&shrunkValues);
+            }
+        }
+    }
+    let best = (0..=n)
+        .min_by(|a, b| values[*a].total_cmp(&values[*b]))
+        .unwrap_or(0);
+    Ok(simplex.swap_remove(best))
+}
+
+// minimizes the objective over the bounds, None when no parameter set that was tried has
+// valid data points or the family accepts none of them (e.g. linear without lower < upper)
+pub fn optimize(
+    ilkTransitions: &[IlkTransitions],
+    evaluationOptions: &EvaluationOptions,
+    options: &OptimizerOptions,
+) -> Result<Option<SearchResult>, Error> {
+    let mut search = Search {
+        ilkTransitions,
+        evaluationOptions,
+        options,
+        seen: HashMap::new(),
+        trace: vec![],
+        best: None,
+    };
+    match options.method {
+        Method::Grid => search.grid()?,
+        Method::NelderMead => search.nelder_mead()?,
+        Method::Random => search.random()?,
+    }
+    Ok(search.best.map(|(best, bestReport)| SearchResult {
+        family: options.family,
+        parameterNames: options.family.parameter_names().to_vec(),
+        bounds: options.bounds.clone(),
+        method: options.method,
+        objective: options.objective,
+        best,
+        bestReport,
+        trace: search.trace,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // convex, with its minimum 0 at (0.3, 0.8)
+    fn bowl(x: &[f64]) -> f64 {
+        (x[0] - 0.3).powi(2) + 4.0 * (x[1] - 0.8).powi(2)
+    }
+
+    #[test]
+    fn nelder_mead_finds_the_minimum_of_a_convex_objective() {
+        let mut evaluations = 0;
+        let best = nelder_mead(2, 200, 1e-9, |points, _| {
+            evaluations += points.len();
+            Ok(points.iter().map(|x| bowl(x)).collect())
+        })
+        .unwrap();
+        assert!((best[0] - 0.3).abs() < 1e-4, "{:?}", best);
+        assert!((best[1] - 0.8).abs() < 1e-4, "{:?}", best);
+        assert!(evaluations < 400);
+    }
+
+    #[test]
+    fn nelder_mead_stays_in_the_unit_cube() {
+        // the minimum is outside the cube, at (1.5, -1)
+        let best = nelder_mead(2, 200, 1e-9, |points, _| {
+            for x in points {
+                assert!(x.iter().all(|x| (0.0..=1.0).contains(x)), "{:?}", x);
+            }
+            Ok(points
+                .iter()
+                .map(|x| (x[0] - 1.5).powi(2) + (x[1] + 1.0).powi(2))
+                .collect())
+        })
+        .unwrap();
+        assert!(
+            (best[0] - 1.0).abs() < 1e-4 && best[1].abs() < 1e-4,
+            "{:?}",
+            best
+        );
+    }
+
+    #[test]
+    fn nelder_mead_stops_after_max_iterations() {
+        let mut iterations = vec![];
+        nelder_mead(1, 3, 0.0, |points, iteration| {
+            iterations.push(iteration);
+            Ok(points.iter().map(|x| bowl(&[x[0], 0.8])).collect())
+        })
+        .unwrap();
+        assert_eq!(iterations.first(), Some(&0));
+        assert_eq!(iterations.iter().max(), Some(&3));
+    }
+
+    #[test]
+    fn nelder_mead_passes_errors_on() {
+        let result = nelder_mead(2, 10, 1e-9, |_, _| {
+            Err(Error::ThreadPool {
+                message: "stopped".to_string(),
+            })
+        });
+        assert!(result.is_err());
+    }
+}
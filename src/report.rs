@@ -0,0 +1,245 @@
+#![allow(non_snake_case)]
+
+use crate::backtest::{BacktestReport, DRatioReport, DataPointTrace};
+use crate::bootstrap::BootstrapReport;
+use crate::error::Error;
+use crate::optimizer::SearchResult;
+use crate::statistics::Percentiles;
+use crate::validation::ValidationReport;
+use serde::Serialize;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// flushed before returning, so a failed write (e.g. a full disk) is an error instead of a
+// truncated file
+fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path).map_err(|e| Error::io(path, e))?);
+    serde_json::to_writer_pretty(&mut writer, value).map_err(|e| Error::io(path, e.into()))?;
+    writer.flush().map_err(|e| Error::io(path, e))
+}
+
+// writes `dratio.json` with the full reports and `dratio.csv` with one row per
+// (strategy, ilk), the aggregate row has an empty ilk column
+pub fn write_reports<P: AsRef<Path>>(
+    outputDir: P,
+    reports: &[BacktestReport],
+) -> Result<(), Error> {
+    let outputDir = outputDir.as_ref();
+    fs::create_dir_all(outputDir).map_err(|e| Error::io(outputDir, e))?;
+
+    write_json(&outputDir.join("dratio.json"), reports)?;
+
+    let path = outputDir.join("dratio.csv");
+    let csvError = |e: csv::Error| Error::io(&path, io::Error::from(e));
+    let mut writer = csv::Writer::from_path(&path).map_err(csvError)?;
+    for report in report_rows(reports) {
+        writer.serialize(CsvRow::new(report)).map_err(csvError)?;
+    }
+    writer.flush().map_err(|e| Error::io(&path, e))?;
+    Ok(())
+}
+
+// csv can't hold nested values, so the statistics are flattened and the histogram is only
+// in dratio.json
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    strategy: &'a str,
+    threshold: Option<f64>,
+    coefficient: Option<f64>,
+    ilk: Option<&'a str>,
+    dRatioMedian: f64,
+    dRatioMean: f64,
+    dRatio: f64,
+    plusSum: f64,
+    minusSum: f64,
+    plusCount: u32,
+    minusCount: u32,
+    validDataPointCount: u32,
+    closedVaultCount: Option<u64>,
+    openedVaultCount: Option<u64>,
+    missingHistoryVaultCount: Option<u64>,
+    dRatioStdDev: Option<f64>,
+    dRatioMin: Option<f64>,
+    dRatioMax: Option<f64>,
+    dRatioP5: Option<f64>,
+    dRatioP10: Option<f64>,
+    dRatioP25: Option<f64>,
+    dRatioP50: Option<f64>,
+    dRatioP75: Option<f64>,
+    dRatioP90: Option<f64>,
+    dRatioP95: Option<f64>,
+    dRatioP99: Option<f64>,
+    dRatioMae: Option<f64>,
+    dRatioRmse: Option<f64>,
+    dRatioBias: Option<f64>,
+    bootstrapDRatioMeanLower: Option<f64>,
+    bootstrapDRatioMeanUpper: Option<f64>,
+    bootstrapDRatioMedianLower: Option<f64>,
+    bootstrapDRatioMedianUpper: Option<f64>,
+    bootstrapPlusCountLower: Option<f64>,
+    bootstrapPlusCountUpper: Option<f64>,
+    bootstrapMinusCountLower: Option<f64>,
+    bootstrapMinusCountUpper: Option<f64>,
+    elapsedMillis: f64,
+}
+
+impl<'a> CsvRow<'a> {
+    fn new(report: &'a DRatioReport) -> CsvRow<'a> {
+        let statistics = &report.dRatioStatistics;
+        let percentile = |p: fn(&Percentiles) -> f64| statistics.percentiles.as_ref().map(p);
+        let bootstrap = |bound: fn(&BootstrapReport) -> f64| report.bootstrap.as_ref().map(bound);
+        CsvRow {
+            strategy: &report.strategy,
+            threshold: report.threshold,
+            coefficient: report.coefficient,
+            ilk: report.ilk.as_deref(),
+            dRatioMedian: report.dRatioMedian,
+            dRatioMean: report.dRatioMean,
+            dRatio: report.dRatio,
+            plusSum: report.plusSum,
+            minusSum: report.minusSum,
+            plusCount: report.plusCount,
+            minusCount: report.minusCount,
+            validDataPointCount: report.validDataPointCount,
+            closedVaultCount: report.closedVaultCount,
+            openedVaultCount: report.openedVaultCount,
+            missingHistoryVaultCount: report.missingHistoryVaultCount,
+            dRatioStdDev: statistics.stdDev,
+            dRatioMin: statistics.min,
+            dRatioMax: statistics.max,
+            dRatioP5: percentile(|p| p.p5),
+            dRatioP10: percentile(|p| p.p10),
+            dRatioP25: percentile(|p| p.p25),
+            dRatioP50: percentile(|p| p.p50),
+            dRatioP75: percentile(|p| p.p75),
+            dRatioP90: percentile(|p| p.p90),
+            dRatioP95: percentile(|p| p.p95),
+            dRatioP99: percentile(|p| p.p99),
+            dRatioMae: statistics.mae,
+            dRatioRmse: statistics.rmse,
+            dRatioBias: statistics.bias,
+            bootstrapDRatioMeanLower: bootstrap(|b| b.dRatioMean.lower),
+            bootstrapDRatioMeanUpper: bootstrap(|b| b.dRatioMean.upper),
+            bootstrapDRatioMedianLower: bootstrap(|b| b.dRatioMedian.lower),
+            bootstrapDRatioMedianUpper: bootstrap(|b| b.dRatioMedian.upper),
+            bootstrapPlusCountLower: bootstrap(|b| b.plusCount.lower),
+            bootstrapPlusCountUpper: bootstrap(|b| b.plusCount.upper),
+            bootstrapMinusCountLower: bootstrap(|b| b.minusCount.lower),
+            bootstrapMinusCountUpper: bootstrap(|b| b.minusCount.upper),
+            elapsedMillis: report.elapsedMillis,
+        }
+    }
+}
+
+// writes `optimize.json` with the search result and `search.csv` with one row per
+// evaluation, one column per parameter
+pub fn write_search<P: AsRef<Path>>(outputDir: P, result: &SearchResult) -> Result<(), Error> {
+    let outputDir = outputDir.as_ref();
+    fs::create_dir_all(outputDir).map_err(|e| Error::io(outputDir, e))?;
+
+    write_json(&outputDir.join("optimize.json"), result)?;
+
+    let path = outputDir.join("search.csv");
+    let csvError = |e: csv::Error| Error::io(&path, io::Error::from(e));
+    let mut writer = csv::Writer::from_path(&path).map_err(csvError)?;
+    let mut header = vec!["evaluation", "round", "strategy"];
+    header.extend(&result.parameterNames);
+    header.extend([
+        "objective",
+        "dRatioMean",
+        "dRatioMedian",
+        "validDataPointCount",
+    ]);
+    writer.write_record(&header).map_err(csvError)?;
+    for step in &result.trace {
+        let mut record = vec![
+            step.evaluation.to_string(),
+            step.round.to_string(),
+            step.strategy.clone(),
+        ];
+        record.extend(step.parameters.iter().map(|x| x.to_string()));
+        record.extend([
+            step.objective.to_string(),
+            step.dRatioMean.to_string(),
+            step.dRatioMedian.to_string(),
+            step.validDataPointCount.to_string(),
+        ]);
+        writer.write_record(&record).map_err(csvError)?;
+    }
+    writer.flush().map_err(|e| Error::io(&path, e))?;
+    Ok(())
+}
+
+// writes `validation.json` with the fitted strategy and the out-of-sample report per fold
+pub fn write_validation<P: AsRef<Path>>(
+    outputDir: P,
+    report: &ValidationReport,
+) -> Result<(), Error> {
+    let outputDir = outputDir.as_ref();
+    fs::create_dir_all(outputDir).map_err(|e| Error::io(outputDir, e))?;
+
+    write_json(&outputDir.join("validation.json"), report)
+}
+
+pub fn report_rows(reports: &[BacktestReport]) -> impl Iterator<Item = &DRatioReport> {
+    reports
+        .iter()
+        .flat_map(|report| report.ilks.iter().chain([&report.aggregate]))
+}
+
+// writes one DataPointTrace per line (JSON Lines)
+pub struct TraceWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl TraceWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<TraceWriter, Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        Ok(TraceWriter {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(File::create(path).map_err(|e| Error::io(path, e))?),
+        })
+    }
+
+    pub fn write(&mut self, trace: &DataPointTrace) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.writer, trace)
+            .map_err(|e| Error::io(&self.path, e.into()))?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(|e| Error::io(&self.path, e))
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.writer.flush().map_err(|e| Error::io(&self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn write_json_reports_a_failed_flush() {
+        // the value fits in the buffer, so only the flush writes and fails
+        let result = write_json(Path::new("/dev/full"), &[1, 2, 3]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn write_json_writes_the_whole_value() {
+        let path = std::env::temp_dir().join(format!("write-json-{}.json", std::process::id()));
+        let values: Vec<u32> = (0..10000).collect();
+        write_json(&path, &values).unwrap();
+        let read: Vec<u32> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read, values);
+    }
+}
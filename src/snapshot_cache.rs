@@ -0,0 +1,358 @@
+#![allow(non_snake_case)]
+
+use crate::error::Error;
+use crate::json_structure::{
+    FixedPoint, StringOrDecimal, StringOrF64, StringOrU64, SubgraphVault, VaultSet,
+};
+use ethnum::I256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// written before the bincode payload, bump the version when the cached types change
+const MAGIC: &[u8; 8] = b"VSCACHE1";
+
+// size and modification time of the json file of a block, a cached block is only used
+// while they are unchanged
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub fileName: String,
+    pub size: u64,
+    pub modifiedSecs: u64,
+    pub modifiedNanos: u32,
+}
+
+impl FileStamp {
+    // None when the file system does not report modification times
+    pub fn of(path: &Path) -> Result<Option<FileStamp>, Error> {
+        let metadata = fs::metadata(path).map_err(|e| Error::io(path, e))?;
+        let Some(modified) = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        else {
+            return Ok(None);
+        };
+        Ok(Some(FileStamp {
+            fileName: path
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().to_string()),
+            size: metadata.len(),
+            modifiedSecs: modified.as_secs(),
+            modifiedNanos: modified.subsec_nanos(),
+        }))
+    }
+}
+
+// f64 value and the exact fixed point value as the two i128 words of an I256
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct CachedDecimal {
+    value: f64,
+    exact: Option<(i128, i128)>,
+}
+
+impl<const DECIMALS: u32> From<StringOrDecimal<DECIMALS>> for CachedDecimal {
+    fn from(decimal: StringOrDecimal<DECIMALS>) -> CachedDecimal {
+        CachedDecimal {
+            value: decimal.0,
+            exact: decimal.1.map(|fixed| fixed.0.into_words()),
+        }
+    }
+}
+
+impl<const DECIMALS: u32> From<CachedDecimal> for StringOrDecimal<DECIMALS> {
+    fn from(decimal: CachedDecimal) -> StringOrDecimal<DECIMALS> {
+        StringOrDecimal(
+            decimal.value,
+            decimal
+                .exact
+                .map(|(hi, lo)| FixedPoint(I256::from_words(hi, lo))),
+        )
+    }
+}
+
+// the json types deserialize from either strings or numbers, which bincode can't do,
+// so the cache has its own plain copies of them
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedVault {
+    id: String,
+    collateral: CachedDecimal,
+    debt: CachedDecimal,
+    cdpId: Option<String>,
+    updatedAt: Option<String>,
+    updatedAtBlock: Option<String>,
+    updatedAtTransaction: Option<String>,
+    safetyLevel: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedVaultSet {
+    ilk: String,
+    timestamp: u64,
+    price: CachedDecimal,
+    rate: CachedDecimal,
+    liquidationRatio: CachedDecimal,
+    vaults: Vec<CachedVault>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedBlock {
+    pub block: String,
+    pub stamp: FileStamp,
+    vaultSets: Vec<CachedVaultSet>,
+}
+
+impl CachedBlock {
+    pub fn new(
+        block: String,
+        stamp: FileStamp,
+        vaultSets: &HashMap<String, VaultSet>,
+    ) -> CachedBlock {
+        let mut ilks: Vec<&String> = vaultSets.keys().collect();
+        ilks.sort();
+        let vaultSets = ilks
+            .into_iter()
+            .map(|ilk| {
+                let vaultSet = &vaultSets[ilk];
+                CachedVaultSet {
+                    ilk: ilk.to_string(),
+                    timestamp: vaultSet.timestamp.0,
+                    price: vaultSet.price.into(),
+                    rate: vaultSet.rate.into(),
+                    liquidationRatio: vaultSet.liquidationRatio.into(),
+                    vaults: vaultSet
+                        .resultArray
+                        .iter()
+                        .map(|vault| CachedVault {
+                            id: vault.id.clone(),
+                            collateral: vault.collateral.into(),
+                            debt: vault.debt.into(),
+                            cdpId: vault.cdpId.clone(),
+                            updatedAt: vault.updatedAt.clone(),
+                            updatedAtBlock: vault.updatedAtBlock.clone(),
+                            updatedAtTransaction: vault.updatedAtTransaction.clone(),
+                            safetyLevel: vault.safetyLevel.0,
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        CachedBlock {
+            block,
+            stamp,
+            vaultSets,
+        }
+    }
+
+    pub fn into_vault_sets(self) -> HashMap<String, VaultSet> {
+        self.vaultSets
+            .into_iter()
+            .map(|vaultSet| {
+                let resultArray = vaultSet
+                    .vaults
+                    .into_iter()
+                    .map(|vault| SubgraphVault {
+                        id: vault.id,
+                        collateral: vault.collateral.into(),
+                        debt: vault.debt.into(),
+                        cdpId: vault.cdpId,
+                        updatedAt: vault.updatedAt,
+                        updatedAtBlock: vault.updatedAtBlock,
+                        updatedAtTransaction: vault.updatedAtTransaction,
+                        safetyLevel: StringOrF64(vault.safetyLevel),
+                    })
+                    .collect();
+                (
+                    vaultSet.ilk,
+                    VaultSet {
+                        timestamp: StringOrU64(vaultSet.timestamp),
+                        resultArray,
+                        price: vaultSet.price.into(),
+                        rate: vaultSet.rate.into(),
+                        liquidationRatio: vaultSet.liquidationRatio.into(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnapshotCache {
+    pub blocks: Vec<CachedBlock>,
+}
+
+// `data/vaultSet` -> `data/vaultSet.cache`, next to the directory so read_dir never sees it
+pub fn snapshot_cache_path(vaultSet: &str) -> PathBuf {
+    let path = Path::new(vaultSet);
+    let path = match path.file_name() {
+        Some(_) => path.to_path_buf(),
+        None => fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+    };
+    let mut fileName = path.file_name().unwrap_or_default().to_os_string();
+    fileName.push(".cache");
+    path.with_file_name(fileName)
+}
+
+fn invalid_data(e: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+pub fn read_snapshot_cache<P: AsRef<Path>>(path: P) -> Result<SnapshotCache, Error> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        return Err(Error::io(
+            path,
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a snapshot cache of this version, rebuild it with `cache build`",
+            ),
+        ));
+    }
+    bincode::deserialize_from(reader).map_err(|e| Error::io(path, invalid_data(e)))
+}
+
+pub fn write_snapshot_cache<P: AsRef<Path>>(path: P, cache: &SnapshotCache) -> Result<(), Error> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(MAGIC).map_err(|e| Error::io(path, e))?;
+    bincode::serialize_into(&mut writer, cache).map_err(|e| Error::io(path, invalid_data(e)))?;
+    writer.flush().map_err(|e| Error::io(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault_sets() -> HashMap<String, VaultSet> {
+        let vaultSet: VaultSet = serde_json::from_value(serde_json::json!({
+            "timestamp": "1670000000",
+            "resultArray": [
+                {
+                    "id": "0x1-ETH-A",
+                    "collateral": "-12.5",
+                    // needs both words of the I256
+                    "debt": "-123456789012345678901234567.123456789012345678",
+                    "cdpId": "1",
+                    "updatedAt": "1669999999",
+                    "updatedAtBlock": null,
+                    "updatedAtTransaction": "0xabc",
+                    "safetyLevel": "-1.5",
+                },
+                {
+                    "id": "0x2-ETH-A",
+                    // no exact wad form
+                    "collateral": "1e100",
+                    "debt": 0.1,
+                    "cdpId": null,
+                    "updatedAt": null,
+                    "updatedAtBlock": null,
+                    "updatedAtTransaction": null,
+                    "safetyLevel": "NaN",
+                },
+            ],
+            "price": "1234.000000000000000000000000001",
+            "rate": "1e60",
+            "liquidationRatio": "1.45",
+        }))
+        .unwrap();
+        HashMap::from([("ETH-A".to_string(), vaultSet)])
+    }
+
+    fn stamp() -> FileStamp {
+        FileStamp {
+            fileName: "vaultSet.json".to_string(),
+            size: 1,
+            modifiedSecs: 2,
+            modifiedNanos: 3,
+        }
+    }
+
+    fn same_decimal<const DECIMALS: u32>(
+        a: StringOrDecimal<DECIMALS>,
+        b: StringOrDecimal<DECIMALS>,
+    ) {
+        assert_eq!(a.0.to_bits(), b.0.to_bits());
+        assert_eq!(a.1, b.1);
+    }
+
+    fn assert_same(a: &HashMap<String, VaultSet>, b: &HashMap<String, VaultSet>) {
+        assert_eq!(a.len(), b.len());
+        for (ilk, a) in a {
+            let b = &b[ilk];
+            assert_eq!(a.timestamp.0, b.timestamp.0);
+            same_decimal(a.price, b.price);
+            same_decimal(a.rate, b.rate);
+            same_decimal(a.liquidationRatio, b.liquidationRatio);
+            assert_eq!(a.resultArray.len(), b.resultArray.len());
+            for (a, b) in a.resultArray.iter().zip(&b.resultArray) {
+                assert_eq!(a.id, b.id);
+                same_decimal(a.collateral, b.collateral);
+                same_decimal(a.debt, b.debt);
+                assert_eq!(a.cdpId, b.cdpId);
+                assert_eq!(a.updatedAt, b.updatedAt);
+                assert_eq!(a.updatedAtBlock, b.updatedAtBlock);
+                assert_eq!(a.updatedAtTransaction, b.updatedAtTransaction);
+                assert_eq!(a.safetyLevel.0.to_bits(), b.safetyLevel.0.to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn cached_block_round_trips_every_value() {
+        let vaultSets = vault_sets();
+        let vault = &vaultSets["ETH-A"].resultArray;
+        assert!(vault[0]
+            .debt
+            .1
+            .is_some_and(|debt| debt.0 < I256::from(i128::MIN)));
+        assert!(vault[1].collateral.1.is_none());
+        assert!(vaultSets["ETH-A"].rate.1.is_none());
+
+        let cachedBlock = CachedBlock::new("100".to_string(), stamp(), &vaultSets);
+        assert_same(&cachedBlock.into_vault_sets(), &vaultSets);
+    }
+
+    #[test]
+    fn snapshot_cache_round_trips_through_a_file() {
+        let vaultSets = vault_sets();
+        let path =
+            std::env::temp_dir().join(format!("snapshot-cache-{}.cache", std::process::id()));
+        let cache = SnapshotCache {
+            blocks: vec![CachedBlock::new("100".to_string(), stamp(), &vaultSets)],
+        };
+        write_snapshot_cache(&path, &cache).unwrap();
+        let read = read_snapshot_cache(&path);
+        fs::write(&path, b"VSCACHE0").unwrap();
+        let stale = read_snapshot_cache(&path);
+        fs::remove_file(&path).unwrap();
+
+        let mut blocks = read.unwrap().blocks;
+        assert_eq!(blocks.len(), 1);
+        let block = blocks.remove(0);
+        assert_eq!((block.block.as_str(), &block.stamp), ("100", &stamp()));
+        assert_same(&block.into_vault_sets(), &vaultSets);
+        assert!(stale.is_err());
+    }
+
+    #[test]
+    fn cache_path_is_next_to_the_directory() {
+        assert_eq!(
+            snapshot_cache_path("data/vaultSet"),
+            PathBuf::from("data/vaultSet.cache")
+        );
+        assert_eq!(
+            snapshot_cache_path("data/vaultSet/"),
+            PathBuf::from("data/vaultSet.cache")
+        );
+    }
+}
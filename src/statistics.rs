@@ -0,0 +1,257 @@
+#![allow(non_snake_case)]
+
+use serde::{Deserialize, Serialize};
+
+// bins of the histogram in a Summary
+pub const HISTOGRAM_BINS: usize = 20;
+
+// percentiles by linear interpolation between the closest ranks
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p5: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+// lower <= value < upper, the last bin also holds values equal to its upper edge
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct HistogramBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+}
+
+// distribution of a list of values, NaN values are only counted in nanCount and every
+// statistic is None when no other value is left
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Summary {
+    pub count: usize,
+    pub nanCount: usize,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    // sample standard deviation, None below two values
+    pub stdDev: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub percentiles: Option<Percentiles>,
+    // the values are errors, so these are mean |x|, sqrt(mean x^2) and mean x
+    pub mae: Option<f64>,
+    pub rmse: Option<f64>,
+    pub bias: Option<f64>,
+    pub histogram: Vec<HistogramBin>,
+}
+
+impl Summary {
+    pub fn of(values: &[f64]) -> Summary {
+        let mut sorted: Vec<f64> = values.iter().copied().filter(|x| !x.is_nan()).collect();
+        sorted.sort_by(f64::total_cmp);
+        let nanCount = values.len() - sorted.len();
+        if sorted.is_empty() {
+            return Summary {
+                nanCount,
+                ..Summary::default()
+            };
+        }
+        let percentile = |p: f64| percentile_of_sorted(&sorted, p).unwrap_or(f64::NAN);
+        let mean = mean(&sorted);
+        Summary {
+            count: sorted.len(),
+            nanCount,
+            mean,
+            median: percentile_of_sorted(&sorted, 50.0),
+            stdDev: std_dev(&sorted),
+            min: sorted.first().copied(),
+            max: sorted.last().copied(),
+            percentiles: Some(Percentiles {
+                p5: percentile(5.0),
+                p10: percentile(10.0),
+                p25: percentile(25.0),
+                p50: percentile(50.0),
+                p75: percentile(75.0),
+                p90: percentile(90.0),
+                p95: percentile(95.0),
+                p99: percentile(99.0),
+            }),
+            mae: mean_of(sorted.iter().map(|x| x.abs())),
+            rmse: mean_of(sorted.iter().map(|x| x * x)).map(f64::sqrt),
+            bias: mean,
+            histogram: histogram(&sorted, HISTOGRAM_BINS),
+        }
+    }
+}
+
+fn mean_of<I: Iterator<Item = f64>>(values: I) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), x| (sum + x, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+// NaN values are ignored
+pub fn mean(values: &[f64]) -> Option<f64> {
+    mean_of(values.iter().copied().filter(|x| !x.is_nan()))
+}
+
+pub fn median(values: &[f64]) -> Option<f64> {
+    percentile(values, 50.0)
+}
+
+// the value at len / 2 of the sorted values, so the upper middle value for an even count,
+// which is how dRatioMedian has always been reported
+pub fn upper_median(values: &[f64]) -> Option<f64> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|x| !x.is_nan()).collect();
+    sorted.sort_by(f64::total_cmp);
+    sorted.get(sorted.len() / 2).copied()
+}
+
+// p in 0..=100, NaN values are ignored
+pub fn percentile(values: &[f64], p: f64) -> Option<f64> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|x| !x.is_nan()).collect();
+    sorted.sort_by(f64::total_cmp);
+    percentile_of_sorted(&sorted, p)
+}
+
+fn percentile_of_sorted(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() || p.is_nan() {
+        return None;
+    }
+    let rank = p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+// sample standard deviation, NaN values are ignored
+pub fn std_dev(values: &[f64]) -> Option<f64> {
+    let values: Vec<f64> = values.iter().copied().filter(|x| !x.is_nan()).collect();
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = mean(&values)?;
+    let squares: f64 = values.iter().map(|x| (x - mean) * (x - mean)).sum();
+    Some((squares / (values.len() - 1) as f64).sqrt())
+}
+
+// `bins` equal bins from min to max, a single bin when all values are equal, NaN and
+// infinite values are left out
+pub fn histogram(values: &[f64], bins: usize) -> Vec<HistogramBin> {
+    let values: Vec<f64> = values.iter().copied().filter(|x| x.is_finite()).collect();
+    let (Some(min), Some(max)) = (
+        values.iter().copied().reduce(f64::min),
+        values.iter().copied().reduce(f64::max),
+    ) else {
+        return vec![];
+    };
+    if bins == 0 {
+        return vec![];
+    }
+    let bins = if min == max { 1 } else { bins };
+    let width = (max - min) / bins as f64;
+    let mut histogram: Vec<HistogramBin> = (0..bins)
+        .map(|index| HistogramBin {
+            lower: min + width * index as f64,
+            upper: if index + 1 == bins {
+                max
+            } else {
+                min + width * (index + 1) as f64
+            },
+            count: 0,
+        })
+        .collect();
+    for x in values {
+        let index = if width > 0.0 {
+            (((x - min) / width) as usize).min(bins - 1)
+        } else {
+            0
+        };
+        histogram[index].count += 1;
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_of_sorted_interpolates_between_ranks() {
+        let sorted = [1.0, 2.0, 4.0, 8.0];
+        assert_eq!(percentile_of_sorted(&sorted, 0.0), Some(1.0));
+        assert_eq!(percentile_of_sorted(&sorted, 100.0), Some(8.0));
+        assert_eq!(percentile_of_sorted(&sorted, 50.0), Some(3.0));
+        assert_eq!(percentile_of_sorted(&sorted, 25.0), Some(1.75));
+        // out of range p is clamped
+        assert_eq!(percentile_of_sorted(&sorted, -10.0), Some(1.0));
+        assert_eq!(percentile_of_sorted(&sorted, 150.0), Some(8.0));
+    }
+
+    #[test]
+    fn percentile_of_sorted_handles_empty_single_and_nan() {
+        assert_eq!(percentile_of_sorted(&[], 50.0), None);
+        assert_eq!(percentile_of_sorted(&[3.5], 0.0), Some(3.5));
+        assert_eq!(percentile_of_sorted(&[3.5], 99.0), Some(3.5));
+        assert_eq!(percentile_of_sorted(&[1.0, 2.0], f64::NAN), None);
+    }
+
+    #[test]
+    fn percentile_ignores_nan_values() {
+        assert_eq!(
+            percentile(&[f64::NAN, 4.0, 2.0, f64::NAN], 100.0),
+            Some(4.0)
+        );
+        assert_eq!(percentile(&[f64::NAN], 50.0), None);
+        assert_eq!(median(&[5.0, f64::NAN, 1.0, 3.0]), Some(3.0));
+        assert_eq!(median(&[4.0, 1.0, 3.0, 2.0]), Some(2.5));
+    }
+
+    #[test]
+    fn upper_median_takes_the_value_at_half_the_length() {
+        assert_eq!(upper_median(&[]), None);
+        assert_eq!(upper_median(&[f64::NAN]), None);
+        assert_eq!(upper_median(&[7.0]), Some(7.0));
+        assert_eq!(upper_median(&[4.0, 1.0, 3.0, 2.0]), Some(3.0));
+        assert_eq!(upper_median(&[3.0, f64::NAN, 1.0, 2.0]), Some(2.0));
+    }
+
+    #[test]
+    fn histogram_of_empty_and_non_finite_values_is_empty() {
+        assert!(histogram(&[], 4).is_empty());
+        assert!(histogram(&[f64::NAN, f64::INFINITY, f64::NEG_INFINITY], 4).is_empty());
+        assert!(histogram(&[1.0, 2.0], 0).is_empty());
+    }
+
+    #[test]
+    fn histogram_of_a_single_value_has_one_bin() {
+        let histogram = histogram(&[2.5, 2.5, f64::NAN], 4);
+        assert_eq!(histogram.len(), 1);
+        assert_eq!(histogram[0].lower, 2.5);
+        assert_eq!(histogram[0].upper, 2.5);
+        assert_eq!(histogram[0].count, 2);
+    }
+
+    #[test]
+    fn histogram_puts_the_max_in_the_last_bin() {
+        let histogram = histogram(&[0.0, 1.0, 2.5, 3.0, 4.0, f64::NAN], 4);
+        let counts: Vec<usize> = histogram.iter().map(|bin| bin.count).collect();
+        assert_eq!(counts, [1, 1, 1, 2]);
+        assert_eq!(histogram[0].lower, 0.0);
+        assert_eq!(histogram[3].upper, 4.0);
+    }
+
+    #[test]
+    fn summary_counts_nan_values_separately() {
+        let summary = Summary::of(&[f64::NAN, f64::NAN]);
+        assert_eq!((summary.count, summary.nanCount), (0, 2));
+        assert!(summary.median.is_none() && summary.histogram.is_empty());
+
+        let summary = Summary::of(&[-1.0, f64::NAN, 3.0]);
+        assert_eq!((summary.count, summary.nanCount), (2, 1));
+        assert_eq!(summary.mean, Some(1.0));
+        assert_eq!(summary.mae, Some(2.0));
+        assert_eq!(summary.min, Some(-1.0));
+        assert_eq!(summary.max, Some(3.0));
+    }
+}
@@ -0,0 +1,247 @@
+#![allow(non_snake_case)]
+
+use crate::json_structure::SubgraphVault;
+
+// Estimates how much of a vault's first-block debt is at risk of liquidation.
+// Only called for vaults that would be below the liquidation ratio at the second price.
+pub trait LiquidationStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn parameters(&self) -> Vec<(&'static str, f64)>;
+
+    fn estimate(
+        &self,
+        vault: &SubgraphVault,
+        priceDropRatio: f64,
+        rate: f64,
+        liquidationRatio: f64,
+    ) -> f64;
+
+    // e.g. "threshold(threshold=300, coefficient=0.5)"
+    fn label(&self) -> String {
+        let parameters: Vec<String> = self
+            .parameters()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        format!("{}({})", self.name(), parameters.join(", "))
+    }
+
+    // None when the strategy has no such parameter or more than one, like the coefficients
+    // of a step table
+    fn parameter(&self, key: &str) -> Option<f64> {
+        let mut values = self
+            .parameters()
+            .into_iter()
+            .filter(|(name, _)| *name == key)
+            .map(|(_, value)| value);
+        match (values.next(), values.next()) {
+            (Some(value), None) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+// so a strategy borrowed from a list can be used where a boxed one is expected
+impl<S: LiquidationStrategy + ?Sized> LiquidationStrategy for &S {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        (**self).parameters()
+    }
+
+    fn estimate(
+        &self,
+        vault: &SubgraphVault,
+        priceDropRatio: f64,
+        rate: f64,
+        liquidationRatio: f64,
+    ) -> f64 {
+        (**self).estimate(vault, priceDropRatio, rate, liquidationRatio)
+    }
+
+    fn label(&self) -> String {
+        (**self).label()
+    }
+}
+
+fn debt_and_safety_level(vault: &SubgraphVault) -> Option<(f64, f64)> {
+    let debt = vault.debt.0;
+    let safetyLevel = vault.safetyLevel.0;
+    if debt.is_nan() || safetyLevel.is_nan() {
+        return None;
+    }
+    Some((debt, safetyLevel))
+}
+
+// debt * coefficient above threshold, full debt otherwise
+#[derive(Clone, Copy, Debug)]
+pub struct ThresholdStrategy {
+    pub threshold: f64,
+    pub coefficient: f64,
+}
+
+impl LiquidationStrategy for ThresholdStrategy {
+    fn name(&self) -> &'static str {
+        "threshold"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("threshold", self.threshold),
+            ("coefficient", self.coefficient),
+        ]
+    }
+
+    fn estimate(&self, vault: &SubgraphVault, _: f64, _: f64, _: f64) -> f64 {
+        match debt_and_safety_level(vault) {
+            Some((debt, safetyLevel)) => {
+                if safetyLevel > self.threshold {
+                    debt * self.coefficient
+                } else {
+                    debt
+                }
+            }
+            None => 0.0,
+        }
+    }
+}
+
+// full debt below lower, debt * coefficient above upper, linear in between
+#[derive(Clone, Copy, Debug)]
+pub struct LinearRampStrategy {
+    pub lower: f64,
+    pub upper: f64,
+    pub coefficient: f64,
+}
+
+impl LiquidationStrategy for LinearRampStrategy {
+    fn name(&self) -> &'static str {
+        "linear"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("lower", self.lower),
+            ("upper", self.upper),
+            ("coefficient", self.coefficient),
+        ]
+    }
+
+    fn estimate(&self, vault: &SubgraphVault, _: f64, _: f64, _: f64) -> f64 {
+        match debt_and_safety_level(vault) {
+            Some((debt, safetyLevel)) => {
+                let factor = if safetyLevel <= self.lower {
+                    1.0
+                } else if safetyLevel >= self.upper {
+                    self.coefficient
+                } else {
+                    let position = (safetyLevel - self.lower) / (self.upper - self.lower);
+                    1.0 + (self.coefficient - 1.0) * position
+                };
+                debt * factor
+            }
+            None => 0.0,
+        }
+    }
+}
+
+// steps are (safetyLevel, coefficient); the last step whose safetyLevel is below the
+// vault's safety level applies, full debt if none does
+#[derive(Clone, Debug)]
+pub struct StepTableStrategy {
+    pub steps: Vec<(f64, f64)>,
+}
+
+impl StepTableStrategy {
+    pub fn new(mut steps: Vec<(f64, f64)>) -> StepTableStrategy {
+        steps.sort_by(|a, b| a.0.total_cmp(&b.0));
+        StepTableStrategy { steps }
+    }
+}
+
+impl LiquidationStrategy for StepTableStrategy {
+    fn name(&self) -> &'static str {
+        "step"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        self.steps
+            .iter()
+            .flat_map(|(safetyLevel, coefficient)| {
+                [("safetyLevel", *safetyLevel), ("coefficient", *coefficient)]
+            })
+            .collect()
+    }
+
+    fn estimate(&self, vault: &SubgraphVault, _: f64, _: f64, _: f64) -> f64 {
+        match debt_and_safety_level(vault) {
+            Some((debt, safetyLevel)) => {
+                let factor = self
+                    .steps
+                    .iter()
+                    .rev()
+                    .find(|(stepSafetyLevel, _)| safetyLevel > *stepSafetyLevel)
+                    .map_or(1.0, |(_, coefficient)| *coefficient);
+                debt * factor
+            }
+            None => 0.0,
+        }
+    }
+}
+
+// smooth version of the threshold rule, factor goes from 1 to coefficient around midpoint
+#[derive(Clone, Copy, Debug)]
+pub struct LogisticStrategy {
+    pub midpoint: f64,
+    pub steepness: f64,
+    pub coefficient: f64,
+}
+
+impl LiquidationStrategy for LogisticStrategy {
+    fn name(&self) -> &'static str {
+        "logistic"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("midpoint", self.midpoint),
+            ("steepness", self.steepness),
+            ("coefficient", self.coefficient),
+        ]
+    }
+
+    fn estimate(&self, vault: &SubgraphVault, _: f64, _: f64, _: f64) -> f64 {
+        match debt_and_safety_level(vault) {
+            Some((debt, safetyLevel)) => {
+                let weight = 1.0 / (1.0 + (self.steepness * (safetyLevel - self.midpoint)).exp());
+                debt * (self.coefficient + (1.0 - self.coefficient) * weight)
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameter_is_none_when_the_key_repeats() {
+        let threshold = ThresholdStrategy {
+            threshold: 100.0,
+            coefficient: 0.3,
+        };
+        assert_eq!(threshold.parameter("threshold"), Some(100.0));
+        assert_eq!(threshold.parameter("coefficient"), Some(0.3));
+        assert_eq!(threshold.parameter("lower"), None);
+
+        let steps = StepTableStrategy::new(vec![(150.0, 0.5), (200.0, 0.2)]);
+        assert_eq!(steps.parameter("coefficient"), None);
+        assert_eq!(steps.parameter("safetyLevel"), None);
+        let step = StepTableStrategy::new(vec![(150.0, 0.5)]);
+        assert_eq!(step.parameter("coefficient"), Some(0.5));
+    }
+}
@@ -0,0 +1,129 @@
+#![allow(non_snake_case)]
+
+use crate::backtest::{IlkSelection, MissingVaultPolicy, VaultStatusCounts};
+use crate::block_index::Window;
+use crate::error::Error;
+use crate::json_structure::{
+    BlockDiffMetadata, StringOrWad, SubgraphVault, VaultTransitionStatus,
+    VaultTransitionWithMetadata,
+};
+use crate::snapshot_cache::FileStamp;
+use crate::vault_filter::VaultFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// one continuing or closed vault, the first-block vault is what a strategy estimates the
+// risk of
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransitionRow {
+    pub vault: SubgraphVault,
+    pub liquidated: bool,
+    pub liquidationTimestamp: Option<u64>,
+    // from the liquidation logs, None when they have no amounts
+    #[serde(default)]
+    pub liquidatedDebt: Option<StringOrWad>,
+    #[serde(default)]
+    pub seizedCollateral: Option<StringOrWad>,
+}
+
+// compact form of the transitions of one (firstBlock, secondBlock, ilk), it does not
+// depend on the strategy so it is built once and shared by every strategy
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransitionTable {
+    pub meta: BlockDiffMetadata,
+    // continuing and closed vaults ordered by vault id, a vault liquidated between the
+    // blocks is often gone from the second snapshot; opened and history-less vaults are
+    // only counted
+    pub rows: Vec<TransitionRow>,
+    pub vaultStatusCounts: VaultStatusCounts,
+}
+
+impl TransitionTable {
+    pub fn new(vaultTransitionWithMetadata: &VaultTransitionWithMetadata) -> TransitionTable {
+        let rows = vaultTransitionWithMetadata
+            .vaultTransition
+            .values()
+            .filter(|vaultTransitionInner| {
+                vaultTransitionInner.status != VaultTransitionStatus::MissingHistory
+            })
+            .filter_map(|vaultTransitionInner| {
+                let first = vaultTransitionInner.first?;
+                Some(TransitionRow {
+                    vault: first.clone(),
+                    liquidated: vaultTransitionInner.liquidated,
+                    liquidationTimestamp: vaultTransitionInner.liquidationTimestamp,
+                    liquidatedDebt: vaultTransitionInner.liquidatedDebt,
+                    seizedCollateral: vaultTransitionInner.seizedCollateral,
+                })
+            })
+            .collect();
+        TransitionTable {
+            meta: vaultTransitionWithMetadata.meta.clone(),
+            rows,
+            vaultStatusCounts: VaultStatusCounts::of(vaultTransitionWithMetadata),
+        }
+    }
+}
+
+// tables of every dataset pair that has the ilk in both snapshots, in dataset order
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IlkTransitions {
+    pub ilk: String,
+    pub tables: Vec<TransitionTable>,
+}
+
+// bump when the tables are built differently, so caches of older builds are rebuilt
+pub const TRANSITION_CACHE_VERSION: u32 = 3;
+
+// everything the tables depend on, a persisted cache is only reused when this matches
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransitionCacheKey {
+    pub version: u32,
+    pub vaultHistory: PathBuf,
+    pub vaultSet: String,
+    // size and modification time of vaultHistory.json and of the json file of every block,
+    // so the cache is rebuilt after the data changes
+    pub vaultHistoryStamp: Option<FileStamp>,
+    pub blockStamps: BTreeMap<String, Option<FileStamp>>,
+    pub ilkSelection: IlkSelection,
+    pub window: Window,
+    pub missingVaultPolicy: MissingVaultPolicy,
+    pub vaultFilter: VaultFilter,
+}
+
+impl TransitionCacheKey {
+    // without modification times a change of the data can't be detected
+    pub fn is_stamped(&self) -> bool {
+        self.vaultHistoryStamp.is_some() && self.blockStamps.values().all(Option::is_some)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransitionCache {
+    pub key: TransitionCacheKey,
+    pub ilkTransitions: Vec<IlkTransitions>,
+}
+
+pub fn read_transition_cache<P: AsRef<Path>>(path: P) -> Result<TransitionCache, Error> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| Error::json(path, &e))
+}
+
+pub fn write_transition_cache<P: AsRef<Path>>(
+    path: P,
+    cache: &TransitionCache,
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+    }
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, cache).map_err(|e| Error::io(path, e.into()))?;
+    writer.flush().map_err(|e| Error::io(path, e))
+}
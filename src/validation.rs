@@ -0,0 +1,375 @@
+#![allow(non_snake_case)]
+
+use crate::backtest::{
+    evaluate_strategies, evaluate_transitions, BacktestReport, EvaluationOptions,
+};
+use crate::error::Error;
+use crate::json_structure::BlockDiffMetadata;
+use crate::optimizer::Objective;
+use crate::statistics;
+use crate::strategy::LiquidationStrategy;
+use crate::transition::IlkTransitions;
+use serde::Serialize;
+
+// how the block pairs are split into train and test periods
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Folds {
+    // one fold, trained on the pairs before splitBlock and tested on the pairs from it on
+    TrainTest { splitBlock: u64 },
+    // consecutive windows of windowBlocks blocks from the first block, fold k is trained on
+    // window k and tested on window k + 1
+    WalkForward { windowBlocks: u64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValidationOptions {
+    pub folds: Folds,
+    // what the fit minimizes on the train pairs, also reported for the test pairs
+    pub objective: Objective,
+    pub underestimationWeight: f64,
+}
+
+impl ValidationOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Folds::WalkForward { windowBlocks: 0 } = self.folds {
+            return Err("validation: walk-forward window must be positive".to_string());
+        }
+        if !(self.underestimationWeight.is_finite() && self.underestimationWeight > 0.0) {
+            return Err("validation: underestimation weight must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+// start <= block < end, a pair is in the range when both of its blocks are, so the train
+// and test pairs of a fold never share a snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct BlockRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl BlockRange {
+    fn contains(&self, meta: &BlockDiffMetadata) -> bool {
+        [&meta.firstBlock, &meta.secondBlock].iter().all(|block| {
+            block
+                .parse::<u64>()
+                .is_ok_and(|block| self.start <= block && block < self.end)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Fold {
+    pub index: usize,
+    pub train: BlockRange,
+    pub test: BlockRange,
+}
+
+impl Folds {
+    // folds over the blocks of the pairs, none without pairs
+    pub fn folds(&self, ilkTransitions: &[IlkTransitions]) -> Vec<Fold> {
+        let blocks: Vec<u64> = ilkTransitions
+            .iter()
+            .flat_map(|ilkTransitions| &ilkTransitions.tables)
+            .flat_map(|table| [&table.meta.firstBlock, &table.meta.secondBlock])
+            .filter_map(|block| block.parse::<u64>().ok())
+            .collect();
+        let (Some(first), Some(last)) = (blocks.iter().min(), blocks.iter().max()) else {
+            return vec![];
+        };
+        let end = last.saturating_add(1);
+        match *self {
+            Folds::TrainTest { splitBlock } => vec![Fold {
+                index: 0,
+                train: BlockRange {
+                    start: *first,
+                    end: splitBlock,
+                },
+                test: BlockRange {
+                    start: splitBlock,
+                    end,
+                },
+            }],
+            Folds::WalkForward { windowBlocks } => {
+                // None once a window would end past u64::MAX, no block can be in it
+                let window = |k: u64| {
+                    Some(BlockRange {
+                        start: first.checked_add(k.checked_mul(windowBlocks)?)?,
+                        end: first.checked_add(k.checked_add(1)?.checked_mul(windowBlocks)?)?,
+                    })
+                };
+                let mut folds: Vec<Fold> = vec![];
+                for k in 0.. {
+                    let (Some(train), Some(test)) = (window(k), window(k + 1)) else {
+                        break;
+                    };
+                    if test.start >= end {
+                        break;
+                    }
+                    folds.push(Fold {
+                        index: k as usize,
+                        train,
+                        test,
+                    });
+                }
+                folds
+            }
+        }
+    }
+}
+
+// the pairs within the range, every ilk is kept even when none of its pairs is
+pub fn select(ilkTransitions: &[IlkTransitions], range: &BlockRange) -> Vec<IlkTransitions> {
+    ilkTransitions
+        .iter()
+        .map(|ilkTransitions| IlkTransitions {
+            ilk: ilkTransitions.ilk.clone(),
+            tables: ilkTransitions
+                .tables
+                .iter()
+                .filter(|table| range.contains(&table.meta))
+                .cloned()
+                .collect(),
+        })
+        .collect()
+}
+
+fn pair_count(ilkTransitions: &[IlkTransitions]) -> usize {
+    ilkTransitions
+        .iter()
+        .map(|ilkTransitions| ilkTransitions.tables.len())
+        .sum()
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FoldReport {
+    pub fold: Fold,
+    pub trainPairCount: usize,
+    pub testPairCount: usize,
+    // None when either period has no pairs or nothing could be fitted
+    pub strategy: Option<String>,
+    // None without valid data points
+    pub trainObjective: Option<f64>,
+    pub testObjective: Option<f64>,
+    // out-of-sample report of the fitted strategy
+    pub test: Option<BacktestReport>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidationReport {
+    pub folds: Folds,
+    pub objective: Objective,
+    pub foldReports: Vec<FoldReport>,
+    // over the folds with a test objective
+    pub meanTestObjective: Option<f64>,
+}
+
+// calibrates a strategy on the train pairs of a fold, None when there is nothing to fit
+pub type Fit<'a> =
+    dyn FnMut(&[IlkTransitions]) -> Result<Option<Box<dyn LiquidationStrategy + 'a>>, Error> + 'a;
+
+// index of the strategy with the lowest objective, the first one on ties, None when no
+// strategy has valid data points
+pub fn fit_best(
+    ilkTransitions: &[IlkTransitions],
+    strategies: &[Box<dyn LiquidationStrategy>],
+    evaluationOptions: &EvaluationOptions,
+    options: &ValidationOptions,
+) -> Result<Option<usize>, Error> {
+    // intervals of the candidates are never looked at
+    let evaluationOptions = EvaluationOptions {
+        bootstrap: None,
+        ..evaluationOptions.clone()
+    };
+    let reports = evaluate_strategies(ilkTransitions, strategies, &evaluationOptions, None)?;
+    let mut best: Option<(usize, f64)> = None;
+    for (index, report) in reports.iter().enumerate() {
+        let objective = options
+            .objective
+            .value(&report.aggregate, options.underestimationWeight);
+        if objective.is_finite() && best.is_none_or(|(_, best)| objective < best) {
+            best = Some((index, objective));
+        }
+    }
+    Ok(best.map(|(index, _)| index))
+}
+
+// fits on the train pairs and scores on the test pairs of every fold
+pub fn cross_validate<'a>(
+    ilkTransitions: &[IlkTransitions],
+    evaluationOptions: &EvaluationOptions,
+    options: &ValidationOptions,
+    fit: &mut Fit<'a>,
+) -> Result<ValidationReport, Error> {
+    let objective = |report: &BacktestReport| {
+        Some(
+            options
+                .objective
+                .value(&report.aggregate, options.underestimationWeight),
+        )
+        .filter(|objective| objective.is_finite())
+    };
+    let mut foldReports: Vec<FoldReport> = vec![];
+    for fold in options.folds.folds(ilkTransitions) {
+        let train = select(ilkTransitions, &fold.train);
+        let test = select(ilkTransitions, &fold.test);
+        let mut foldReport = FoldReport {
+            fold,
+            trainPairCount: pair_count(&train),
+            testPairCount: pair_count(&test),
+            strategy: None,
+            trainObjective: None,
+            testObjective: None,
+            test: None,
+        };
+        if foldReport.trainPairCount > 0 && foldReport.testPairCount > 0 {
+            if let Some(strategy) = fit(&train)? {
+                let trainReport =
+                    evaluate_transitions(&train, strategy.as_ref(), evaluationOptions, None);
+                let testReport =
+                    evaluate_transitions(&test, strategy.as_ref(), evaluationOptions, None);
+                foldReport.strategy = Some(strategy.label());
+                foldReport.trainObjective = objective(&trainReport);
+                foldReport.testObjective = objective(&testReport);
+                foldReport.test = Some(testReport);
+            }
+        }
+        foldReports.push(foldReport);
+    }
+    let testObjectives: Vec<f64> = foldReports
+        .iter()
+        .filter_map(|foldReport| foldReport.testObjective)
+        .collect();
+    Ok(ValidationReport {
+        folds: options.folds,
+        objective: options.objective,
+        meanTestObjective: statistics::mean(&testObjectives),
+        foldReports,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transition::TransitionTable;
+
+    fn table(firstBlock: u64, secondBlock: u64) -> TransitionTable {
+        serde_json::from_value(serde_json::json!({
+            "meta": {
+                "firstBlock": firstBlock.to_string(),
+                "firstTimestamp": firstBlock,
+                "firstPrice": "2000",
+                "firstRate": "1",
+                "firstLiquidationRatio": "1.5",
+                "secondBlock": secondBlock.to_string(),
+                "secondTimestamp": secondBlock,
+                "secondPrice": "1500",
+                "secondRate": "1",
+                "secondLiquidationRatio": "1.5",
+            },
+            "rows": [],
+            "vaultStatusCounts": {"closed": 0, "opened": 0, "missingHistory": 0},
+        }))
+        .unwrap()
+    }
+
+    fn transitions(pairs: &[(u64, u64)]) -> Vec<IlkTransitions> {
+        vec![IlkTransitions {
+            ilk: "ETH-A".to_string(),
+            tables: pairs
+                .iter()
+                .map(|(first, second)| table(*first, *second))
+                .collect(),
+        }]
+    }
+
+    fn range(start: u64, end: u64) -> BlockRange {
+        BlockRange { start, end }
+    }
+
+    #[test]
+    fn train_test_splits_at_the_split_block() {
+        let ilkTransitions = transitions(&[(100, 150), (150, 250), (250, 300)]);
+        let folds = Folds::TrainTest { splitBlock: 200 }.folds(&ilkTransitions);
+        assert_eq!(
+            folds,
+            [Fold {
+                index: 0,
+                train: range(100, 200),
+                test: range(200, 301),
+            }]
+        );
+    }
+
+    #[test]
+    fn walk_forward_windows_follow_each_other_up_to_the_last_block() {
+        let ilkTransitions = transitions(&[(100, 110), (110, 130), (130, 140)]);
+        let folds = Folds::WalkForward { windowBlocks: 15 }.folds(&ilkTransitions);
+        let ranges: Vec<(BlockRange, BlockRange)> =
+            folds.iter().map(|fold| (fold.train, fold.test)).collect();
+        assert_eq!(
+            ranges,
+            [
+                (range(100, 115), range(115, 130)),
+                (range(115, 130), range(130, 145)),
+            ]
+        );
+        // the last block starts a window of its own
+        let folds = Folds::WalkForward { windowBlocks: 20 }.folds(&ilkTransitions);
+        assert_eq!(folds.len(), 2);
+        assert_eq!(folds[1].test, range(140, 160));
+        // one window covers everything, nothing to test on
+        assert!(Folds::WalkForward { windowBlocks: 41 }
+            .folds(&ilkTransitions)
+            .is_empty());
+    }
+
+    #[test]
+    fn walk_forward_stops_before_the_windows_overflow() {
+        let ilkTransitions = transitions(&[(100, 110)]);
+        for windowBlocks in [u64::MAX, u64::MAX / 2, 1 << 63] {
+            assert!(Folds::WalkForward { windowBlocks }
+                .folds(&ilkTransitions)
+                .is_empty());
+        }
+        let ilkTransitions = transitions(&[(u64::MAX - 10, u64::MAX - 1)]);
+        // the third window would end past u64::MAX
+        let folds = Folds::WalkForward { windowBlocks: 4 }.folds(&ilkTransitions);
+        assert_eq!(folds.len(), 1);
+        assert_eq!(folds[0].test, range(u64::MAX - 6, u64::MAX - 2));
+    }
+
+    #[test]
+    fn folds_without_pairs_are_empty() {
+        assert!(Folds::TrainTest { splitBlock: 5 }
+            .folds(&transitions(&[]))
+            .is_empty());
+    }
+
+    #[test]
+    fn select_keeps_pairs_with_both_blocks_in_the_range() {
+        let ilkTransitions = transitions(&[(100, 150), (150, 200), (190, 210), (200, 250)]);
+        let selected = select(&ilkTransitions, &range(100, 200));
+        let blocks: Vec<(&str, &str)> = selected[0]
+            .tables
+            .iter()
+            .map(|table| {
+                (
+                    table.meta.firstBlock.as_str(),
+                    table.meta.secondBlock.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(blocks, [("100", "150")]);
+
+        let selected = select(&ilkTransitions, &range(150, 251));
+        assert_eq!(pair_count(&selected), 3);
+
+        // the ilk is kept without pairs
+        let selected = select(&ilkTransitions, &range(300, 400));
+        assert_eq!(selected.len(), 1);
+        assert_eq!(pair_count(&selected), 0);
+    }
+}
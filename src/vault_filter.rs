@@ -0,0 +1,161 @@
+#![allow(non_snake_case)]
+
+use crate::json_structure::{SubgraphVault, VaultSet};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// which vaults of a snapshot take part in the transitions, by default every vault with
+// positive collateral and debt
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VaultFilter {
+    // debt >= minDebt
+    #[serde(default)]
+    pub minDebt: Option<f64>,
+    // collateral >= minCollateral
+    #[serde(default)]
+    pub minCollateral: Option<f64>,
+    // safetyLevel >= minSafetyLevel
+    #[serde(default)]
+    pub minSafetyLevel: Option<f64>,
+    // vaults with debt * rate below the dust limit, in DAI, are left out
+    #[serde(default)]
+    pub dust: Option<f64>,
+    // only these vault ids when not empty
+    #[serde(default)]
+    pub includeVaults: BTreeSet<String>,
+    #[serde(default)]
+    pub excludeVaults: BTreeSet<String>,
+    // the check used before the filter existed, which only looked at the collateral
+    // (debt was read from the collateral field), so vaults without debt were included
+    #[serde(default)]
+    pub legacy: bool,
+}
+
+impl VaultFilter {
+    pub fn accepts(&self, vault: &SubgraphVault, vaultSet: &VaultSet) -> bool {
+        let collateral = vault.collateral.0;
+        let debt = vault.debt.0;
+        let eligible = if self.legacy {
+            collateral > 0.0
+        } else {
+            collateral > 0.0 && debt > 0.0
+        };
+        eligible
+            && self.minDebt.is_none_or(|minDebt| debt >= minDebt)
+            && self
+                .minCollateral
+                .is_none_or(|minCollateral| collateral >= minCollateral)
+            && self
+                .minSafetyLevel
+                .is_none_or(|minSafetyLevel| vault.safetyLevel.0 >= minSafetyLevel)
+            && self.dust.is_none_or(|dust| debt * vaultSet.rate.0 >= dust)
+            && (self.includeVaults.is_empty() || self.includeVaults.contains(&vault.id))
+            && !self.excludeVaults.contains(&vault.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault(id: &str, collateral: &str, debt: &str, safetyLevel: &str) -> SubgraphVault {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "collateral": collateral,
+            "debt": debt,
+            "cdpId": null,
+            "updatedAt": null,
+            "updatedAtBlock": null,
+            "updatedAtTransaction": null,
+            "safetyLevel": safetyLevel,
+        }))
+        .unwrap()
+    }
+
+    fn vault_set(rate: &str) -> VaultSet {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": "1000",
+            "resultArray": [],
+            "price": "2000",
+            "rate": rate,
+            "liquidationRatio": "1.5",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn zero_debt_vaults_are_rejected_unless_legacy() {
+        let vaultSet = vault_set("1");
+        let noDebt = vault("0x1", "10", "0", "150");
+        let noCollateral = vault("0x2", "0", "100", "0");
+        let filter = VaultFilter::default();
+        assert!(!filter.accepts(&noDebt, &vaultSet));
+        assert!(!filter.accepts(&noCollateral, &vaultSet));
+        assert!(filter.accepts(&vault("0x3", "10", "100", "150"), &vaultSet));
+
+        let legacy = VaultFilter {
+            legacy: true,
+            ..VaultFilter::default()
+        };
+        assert!(legacy.accepts(&noDebt, &vaultSet));
+        assert!(!legacy.accepts(&noCollateral, &vaultSet));
+    }
+
+    #[test]
+    fn minimums_include_their_boundary() {
+        let vaultSet = vault_set("1");
+        let atMinimums = vault("0x1", "10", "100", "150");
+        let filter = VaultFilter {
+            minDebt: Some(100.0),
+            minCollateral: Some(10.0),
+            minSafetyLevel: Some(150.0),
+            ..VaultFilter::default()
+        };
+        assert!(filter.accepts(&atMinimums, &vaultSet));
+        assert!(!filter.accepts(&vault("0x1", "10", "99.99", "150"), &vaultSet));
+        assert!(!filter.accepts(&vault("0x1", "9.99", "100", "150"), &vaultSet));
+        assert!(!filter.accepts(&vault("0x1", "10", "100", "149.99"), &vaultSet));
+    }
+
+    #[test]
+    fn dust_compares_debt_times_rate() {
+        let filter = VaultFilter {
+            dust: Some(100.0),
+            ..VaultFilter::default()
+        };
+        let vaultAtDust = vault("0x1", "10", "80", "150");
+        assert!(filter.accepts(&vaultAtDust, &vault_set("1.25")));
+        assert!(!filter.accepts(&vaultAtDust, &vault_set("1.2")));
+    }
+
+    #[test]
+    fn include_and_exclude_vaults() {
+        let vaultSet = vault_set("1");
+        let (a, b) = (
+            vault("0xa", "10", "100", "150"),
+            vault("0xb", "10", "100", "150"),
+        );
+        let include = VaultFilter {
+            includeVaults: BTreeSet::from(["0xa".to_string()]),
+            ..VaultFilter::default()
+        };
+        assert!(include.accepts(&a, &vaultSet));
+        assert!(!include.accepts(&b, &vaultSet));
+
+        let exclude = VaultFilter {
+            excludeVaults: BTreeSet::from(["0xa".to_string()]),
+            ..VaultFilter::default()
+        };
+        assert!(!exclude.accepts(&a, &vaultSet));
+        assert!(exclude.accepts(&b, &vaultSet));
+
+        // excluding wins over including
+        let both = VaultFilter {
+            includeVaults: BTreeSet::from(["0xa".to_string()]),
+            excludeVaults: BTreeSet::from(["0xa".to_string()]),
+            ..VaultFilter::default()
+        };
+        assert!(!both.accepts(&a, &vaultSet));
+    }
+}